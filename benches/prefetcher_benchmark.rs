@@ -2,21 +2,17 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion, Benchmark
 use ml_prefetcher::PredictivePrefetcher;
 use rand::Rng;
 use std::iter;
-use tokio::runtime::Runtime;
 
 fn benchmark_sequential_pattern(c: &mut Criterion) {
     let mut group = c.benchmark_group("Sequential Pattern");
-    let rt = Runtime::new().unwrap();
     
     for size in [100, 1000, 10000].iter() {
         group.bench_with_input(BenchmarkId::from_parameter(size), size, |b, &size| {
             b.iter(|| {
                 let mut prefetcher = PredictivePrefetcher::new(4);
-                rt.block_on(async {
-                    for i in 0..size {
-                        black_box(prefetcher.access(i as i32).await);
-                    }
-                })
+                for i in 0..size {
+                    black_box(prefetcher.access_sync(i));
+                }
             })
         });
     }
@@ -26,7 +22,6 @@ fn benchmark_sequential_pattern(c: &mut Criterion) {
 
 fn benchmark_strided_pattern(c: &mut Criterion) {
     let mut group = c.benchmark_group("Strided Pattern");
-    let rt = Runtime::new().unwrap();
     let strides = [2, 4, 8];
     
     for &size in &[100, 1000, 10000] {
@@ -37,11 +32,9 @@ fn benchmark_strided_pattern(c: &mut Criterion) {
                 |b, &(size, stride)| {
                     b.iter(|| {
                         let mut prefetcher = PredictivePrefetcher::new(4);
-                        rt.block_on(async {
-                            for i in (0..size).step_by(stride) {
-                                black_box(prefetcher.access(i as i32).await);
-                            }
-                        })
+                        for i in (0..size).step_by(stride) {
+                            black_box(prefetcher.access_sync(i));
+                        }
                     })
                 }
             );
@@ -53,9 +46,8 @@ fn benchmark_strided_pattern(c: &mut Criterion) {
 
 fn benchmark_repeated_pattern(c: &mut Criterion) {
     let mut group = c.benchmark_group("Repeated Pattern");
-    let rt = Runtime::new().unwrap();
     
-    let patterns = vec![
+    let patterns = [
         vec![1_i32, 2, 3],
         vec![1, 2, 3, 4],
         vec![1, 2, 3, 4, 5]
@@ -74,11 +66,9 @@ fn benchmark_repeated_pattern(c: &mut Criterion) {
                         .take(pattern.len() * 100)
                         .copied()
                         .collect::<Vec<_>>();
-                    rt.block_on(async {
-                        for &addr in &repeated {
-                            black_box(prefetcher.access(addr).await);
-                        }
-                    })
+                    for &addr in &repeated {
+                        black_box(prefetcher.access_sync(addr));
+                    }
                 })
             }
         );
@@ -89,7 +79,6 @@ fn benchmark_repeated_pattern(c: &mut Criterion) {
 
 fn benchmark_random_pattern(c: &mut Criterion) {
     let mut group = c.benchmark_group("Random Pattern");
-    let rt = Runtime::new().unwrap();
     
     for &size in &[100, 1000, 10000] {
         group.bench_function(BenchmarkId::from_parameter(size), |b| {
@@ -100,11 +89,9 @@ fn benchmark_random_pattern(c: &mut Criterion) {
                 
             b.iter(|| {
                 let mut prefetcher = PredictivePrefetcher::new(4);
-                rt.block_on(async {
-                    for &addr in &addresses {
-                        black_box(prefetcher.access(addr).await);
-                    }
-                })
+                for &addr in &addresses {
+                    black_box(prefetcher.access_sync(addr));
+                }
             })
         });
     }
@@ -114,15 +101,13 @@ fn benchmark_random_pattern(c: &mut Criterion) {
 
 fn benchmark_mixed_pattern(c: &mut Criterion) {
     let mut group = c.benchmark_group("Mixed Pattern");
-    let rt = Runtime::new().unwrap();
     
     for &size in &[100, 1000, 10000] {
         group.bench_function(BenchmarkId::from_parameter(size), |b| {
             // Create a mix of different patterns
             let sequential: Vec<i32> = (0..size/4).map(|x| x as i32).collect();
             let strided: Vec<i32> = (0..size/4).map(|x| (x * 2) as i32).collect();
-            let repeated: Vec<i32> = iter::repeat(vec![1, 2, 3, 4])
-                .take(size/16)
+            let repeated: Vec<i32> = iter::repeat_n(vec![1, 2, 3, 4], size/16)
                 .flatten()
                 .collect();
             let mut rng = rand::thread_rng();
@@ -139,11 +124,9 @@ fn benchmark_mixed_pattern(c: &mut Criterion) {
             
             b.iter(|| {
                 let mut prefetcher = PredictivePrefetcher::new(4);
-                rt.block_on(async {
-                    for &addr in &mixed {
-                        black_box(prefetcher.access(addr).await);
-                    }
-                })
+                for &addr in &mixed {
+                    black_box(prefetcher.access_sync(addr));
+                }
             })
         });
     }
@@ -153,7 +136,6 @@ fn benchmark_mixed_pattern(c: &mut Criterion) {
 
 fn benchmark_pattern_transition(c: &mut Criterion) {
     let mut group = c.benchmark_group("Pattern Transition");
-    let rt = Runtime::new().unwrap();
     
     for &size in &[100, 1000, 10000] {
         group.bench_function(BenchmarkId::from_parameter(size), |b| {
@@ -169,8 +151,7 @@ fn benchmark_pattern_transition(c: &mut Criterion) {
             pattern.extend((0..section_size).map(|x| (x * 2) as i32));
             
             // Repeated
-            pattern.extend(iter::repeat(vec![1, 2, 3, 4])
-                .take(section_size/4)
+            pattern.extend(iter::repeat_n(vec![1, 2, 3, 4], section_size/4)
                 .flatten());
             
             // Random
@@ -180,11 +161,9 @@ fn benchmark_pattern_transition(c: &mut Criterion) {
             
             b.iter(|| {
                 let mut prefetcher = PredictivePrefetcher::new(4);
-                rt.block_on(async {
-                    for &addr in &pattern {
-                        black_box(prefetcher.access(addr).await);
-                    }
-                })
+                for &addr in &pattern {
+                    black_box(prefetcher.access_sync(addr));
+                }
             })
         });
     }
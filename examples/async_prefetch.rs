@@ -1,4 +1,4 @@
-use ml_prefetcher::PredictivePrefetcher;
+use ml_prefetcher::{DeliveryPolicy, PredictivePrefetcher};
 use tokio::time::sleep;
 use std::time::Duration;
 
@@ -9,7 +9,9 @@ async fn main() {
         8,      // larger history for better pattern detection
         0.2,    // start predicting early
         4       // max window size
-    );
+    )
+    // Never stall accesses on the slow handler below, keep the newest batches
+    .with_delivery(DeliveryPolicy::DropOldest, 16);
     
     // Start async predictor
     let mut rx = prefetcher.start_async_predictor().await;
@@ -83,6 +85,7 @@ async fn main() {
     println!("Hits: {}", hits);
     println!("Misses: {}", misses);
     println!("Accuracy: {:.2}%", accuracy * 100.0);
+    println!("Dropped batches: {}", prefetcher.dropped_batches());
 
     // Clean up
     drop(prefetcher);
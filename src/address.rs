@@ -0,0 +1,89 @@
+use num_traits::PrimInt;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+
+/// An address the prefetcher can track: a virtual address, file offset,
+/// block number or anything else that behaves like an unsigned position.
+///
+/// Strides are always expressed as `i64`. Arithmetic near the ends of the
+/// address space never panics: `checked_offset` reports overflow and
+/// `delta`/`wrapping_offset` wrap.
+pub trait Address: PrimInt + Hash + Debug + Display + Send + Sync + 'static {
+    /// Signed distance `self - other`, wrapping around the address space.
+    fn delta(self, other: Self) -> i64;
+
+    /// `self + delta`, or `None` if the result leaves the address space.
+    fn checked_offset(self, delta: i64) -> Option<Self>;
+
+    /// `self + delta`, wrapping around the address space.
+    fn wrapping_offset(self, delta: i64) -> Self;
+
+    /// Raw bits of the address, used for hashing and region arithmetic.
+    fn to_bits(self) -> u64;
+
+    /// Inverse of `to_bits`, truncating to the width of the type.
+    fn from_bits(bits: u64) -> Self;
+}
+
+macro_rules! impl_unsigned_address {
+    ($($ty:ty => $signed:ty),*) => {
+        $(
+            impl Address for $ty {
+                fn delta(self, other: Self) -> i64 {
+                    self.wrapping_sub(other) as $signed as i64
+                }
+
+                fn checked_offset(self, delta: i64) -> Option<Self> {
+                    if delta >= 0 {
+                        <$ty>::try_from(delta).ok().and_then(|d| self.checked_add(d))
+                    } else {
+                        <$ty>::try_from(delta.unsigned_abs()).ok().and_then(|d| self.checked_sub(d))
+                    }
+                }
+
+                fn wrapping_offset(self, delta: i64) -> Self {
+                    self.wrapping_add(delta as $ty)
+                }
+
+                fn to_bits(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_bits(bits: u64) -> Self {
+                    bits as $ty
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_signed_address {
+    ($($ty:ty),*) => {
+        $(
+            impl Address for $ty {
+                fn delta(self, other: Self) -> i64 {
+                    self.wrapping_sub(other) as i64
+                }
+
+                fn checked_offset(self, delta: i64) -> Option<Self> {
+                    <$ty>::try_from(delta).ok().and_then(|d| self.checked_add(d))
+                }
+
+                fn wrapping_offset(self, delta: i64) -> Self {
+                    self.wrapping_add(delta as $ty)
+                }
+
+                fn to_bits(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_bits(bits: u64) -> Self {
+                    bits as $ty
+                }
+            }
+        )*
+    };
+}
+
+impl_unsigned_address!(u32 => i32, u64 => i64, usize => isize);
+impl_signed_address!(i32, i64);
@@ -0,0 +1,168 @@
+use std::collections::VecDeque;
+
+use crate::address::Address;
+use crate::predictor::{walk, AccessContext, Candidate, Predictor};
+use crate::prefetcher::PatternType;
+
+const RECENT_SIZE: usize = 256;
+const MAX_OFFSET: i64 = 256;
+const SCORE_MAX: u32 = 31;
+const ROUND_MAX: u32 = 100;
+const BAD_SCORE: u32 = 1;
+
+// Offsets up to 256 whose only prime factors are 2, 3 and 5, as in the
+// original best-offset prefetcher
+fn candidate_offsets() -> Vec<i64> {
+    (1..=MAX_OFFSET)
+        .filter(|&offset| {
+            let mut rest = offset;
+            for factor in [2, 3, 5] {
+                while rest % factor == 0 {
+                    rest /= factor;
+                }
+            }
+            rest == 1
+        })
+        .collect()
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct LearnerState {
+    scores: Vec<u32>,
+    recent: Vec<Option<u64>>,
+    delayed: VecDeque<u64>,
+    next: usize,
+    round: u32,
+    best: Option<(i64, u32)>,
+}
+
+/// Best-offset prefetching: one offset, learned globally, for every access.
+///
+/// Recent accesses are kept in a small table. Each access `x` tests the next
+/// candidate offset `d` in turn and scores it if `x - d` is in the table,
+/// i.e. if prefetching with that offset would have covered `x`. A round ends
+/// once an offset reaches the maximum score or every offset has been tested
+/// 100 times; its best offset is used during the next round, unless it
+/// scored too low, which turns prefetching off. Unlike the other predictors
+/// it ignores streams, so it also covers accesses a per-stream detector
+/// would see as interleaved.
+pub struct BestOffsetPredictor {
+    offsets: Vec<i64>,
+    lead: usize,
+    state: LearnerState,
+}
+
+impl BestOffsetPredictor {
+    /// A learner that only scores an offset if the access it would have
+    /// prefetched from came at least `lead` accesses earlier, so that the
+    /// chosen offset leaves time for the prefetch to arrive.
+    pub fn new(lead: usize) -> Self {
+        let offsets = candidate_offsets();
+        BestOffsetPredictor {
+            lead,
+            state: LearnerState {
+                scores: vec![0; offsets.len()],
+                recent: vec![None; RECENT_SIZE],
+                delayed: VecDeque::with_capacity(lead + 1),
+                next: 0,
+                round: 0,
+                best: None,
+            },
+            offsets,
+        }
+    }
+
+    /// Offset chosen in the last round, if prefetching is on.
+    pub fn best_offset(&self) -> Option<i64> {
+        self.state.best.map(|(offset, _)| offset)
+    }
+
+    fn slot(bits: u64) -> usize {
+        (bits.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 56) as usize % RECENT_SIZE
+    }
+
+    fn end_round(&mut self) {
+        let state = &mut self.state;
+        // The smallest of equally scored offsets wins
+        let mut best = 0;
+        for (index, &score) in state.scores.iter().enumerate() {
+            if score > state.scores[best] {
+                best = index;
+            }
+        }
+        let score = state.scores[best];
+        state.best = (score > BAD_SCORE).then_some((self.offsets[best], score));
+        state.scores.fill(0);
+        state.next = 0;
+        state.round = 0;
+    }
+}
+
+impl Default for BestOffsetPredictor {
+    fn default() -> Self {
+        BestOffsetPredictor::new(0)
+    }
+}
+
+impl<A: Address> Predictor<A> for BestOffsetPredictor {
+    fn pattern_type(&self) -> PatternType {
+        PatternType::BestOffset
+    }
+
+    fn observe(&mut self, access: &AccessContext<A>) {
+        let bits = access.address.to_bits();
+        let state = &mut self.state;
+
+        // Would prefetching with the offset under test have covered this access?
+        let base = bits.wrapping_sub(self.offsets[state.next] as u64);
+        if state.recent[Self::slot(base)] == Some(base) {
+            state.scores[state.next] += 1;
+        }
+        let mut round_over = state.scores[state.next] >= SCORE_MAX;
+        state.next += 1;
+        if state.next == self.offsets.len() {
+            state.next = 0;
+            state.round += 1;
+            round_over |= state.round >= ROUND_MAX;
+        }
+
+        state.delayed.push_back(bits);
+        while state.delayed.len() > self.lead {
+            if let Some(old) = state.delayed.pop_front() {
+                state.recent[Self::slot(old)] = Some(old);
+            }
+        }
+        if round_over {
+            self.end_round();
+        }
+    }
+
+    fn predict(&mut self, access: &AccessContext<A>, max_candidates: usize) -> Vec<Candidate<A>> {
+        match self.state.best {
+            // Further candidates continue with the same offset, so the
+            // prefetch degree applies as for the other patterns
+            Some((offset, score)) => walk(
+                access.address,
+                std::iter::repeat_n(offset, max_candidates),
+                score as f64 / SCORE_MAX as f64,
+            ),
+            None => Vec::new(),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn save_state(&self) -> Option<Vec<u8>> {
+        bincode::serialize(&self.state).ok()
+    }
+
+    #[cfg(feature = "serde")]
+    fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        let state: LearnerState = bincode::deserialize(state).map_err(|err| err.to_string())?;
+        let fits = state.scores.len() == self.offsets.len() && state.recent.len() == RECENT_SIZE;
+        if !fits || state.next >= self.offsets.len() {
+            return Err("best-offset state does not fit the offset list".to_string());
+        }
+        self.state = state;
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+//! Set-associative cache model for measuring how many demand misses the
+//! prefetcher's predictions would actually remove.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::address::Address;
+use crate::prefetcher::PredictivePrefetcher;
+use crate::trace::{replay_each, ReplayOptions, ReplayReport, TraceError, TraceRecord};
+
+/// Which line of a full set is replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Replacement {
+    #[default]
+    Lru,
+    /// A pseudo-random way, seeded so runs are reproducible.
+    Random,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheConfig {
+    /// Capacity in bytes.
+    pub size: u64,
+    pub associativity: usize,
+    /// Line size in bytes.
+    pub line_size: u64,
+    pub replacement: Replacement,
+}
+
+impl Default for CacheConfig {
+    /// A 32 KiB, 8-way cache with 64 byte lines and LRU replacement.
+    fn default() -> Self {
+        CacheConfig {
+            size: 32 * 1024,
+            associativity: 8,
+            line_size: 64,
+            replacement: Replacement::Lru,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn sets(&self) -> usize {
+        let set_size = self.associativity.max(1) as u64 * self.line_size.max(1);
+        (self.size / set_size).max(1) as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Demand accesses.
+    pub accesses: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// Lines brought in by prefetches.
+    pub prefetch_fills: u64,
+    /// Prefetches of lines that were already cached.
+    pub redundant_prefetches: u64,
+    /// Prefetched lines later hit by a demand access.
+    pub useful_prefetches: u64,
+    /// Prefetched lines evicted before any demand access used them.
+    pub unused_prefetches: u64,
+    /// Lines used by demand accesses that were evicted to make room for a
+    /// prefetch (cache pollution).
+    pub prefetch_evictions: u64,
+}
+
+impl CacheStats {
+    pub fn miss_rate(&self) -> f64 {
+        if self.accesses > 0 {
+            self.misses as f64 / self.accesses as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Demand misses per thousand instructions.
+    pub fn mpki(&self, instructions: u64) -> f64 {
+        if instructions > 0 {
+            self.misses as f64 * 1000.0 / instructions as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    tag: u64,
+    last_use: u64,
+    // Filled by a prefetch and not used by a demand access yet
+    prefetched: bool,
+}
+
+/// A set-associative cache of byte addresses.
+pub struct Cache {
+    config: CacheConfig,
+    sets: Vec<Vec<Line>>,
+    clock: u64,
+    rng: StdRng,
+    stats: CacheStats,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Self {
+        Cache {
+            config,
+            sets: vec![Vec::with_capacity(config.associativity.max(1)); config.sets()],
+            clock: 0,
+            rng: StdRng::seed_from_u64(0),
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    fn locate(&self, address: u64) -> (usize, u64) {
+        let line = address / self.config.line_size.max(1);
+        ((line % self.sets.len() as u64) as usize, line)
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        let (set, tag) = self.locate(address);
+        self.sets[set].iter().any(|line| line.tag == tag)
+    }
+
+    /// Demand access to `address`. Returns whether it hit; a miss fills the
+    /// line.
+    pub fn access(&mut self, address: u64) -> bool {
+        self.clock += 1;
+        self.stats.accesses += 1;
+        let (set, tag) = self.locate(address);
+        if let Some(line) = self.sets[set].iter_mut().find(|line| line.tag == tag) {
+            line.last_use = self.clock;
+            if line.prefetched {
+                line.prefetched = false;
+                self.stats.useful_prefetches += 1;
+            }
+            self.stats.hits += 1;
+            return true;
+        }
+
+        self.stats.misses += 1;
+        self.fill(set, Line { tag, last_use: self.clock, prefetched: false });
+        false
+    }
+
+    /// Prefetch the line holding `address`. Returns `false` if it was
+    /// already cached.
+    pub fn prefetch(&mut self, address: u64) -> bool {
+        self.clock += 1;
+        let (set, tag) = self.locate(address);
+        if self.sets[set].iter().any(|line| line.tag == tag) {
+            self.stats.redundant_prefetches += 1;
+            return false;
+        }
+
+        self.stats.prefetch_fills += 1;
+        if let Some(victim) = self.fill(set, Line { tag, last_use: self.clock, prefetched: true }) {
+            if !victim.prefetched {
+                self.stats.prefetch_evictions += 1;
+            }
+        }
+        true
+    }
+
+    /// Insert `line` into `set`, returning the evicted line if the set was
+    /// full.
+    fn fill(&mut self, set: usize, line: Line) -> Option<Line> {
+        let ways = &mut self.sets[set];
+        if ways.len() < self.config.associativity.max(1) {
+            ways.push(line);
+            return None;
+        }
+
+        let index = match self.config.replacement {
+            Replacement::Lru => ways.iter()
+                .enumerate()
+                .min_by_key(|(_, line)| line.last_use)
+                .map_or(0, |(index, _)| index),
+            Replacement::Random => self.rng.gen_range(0..ways.len()),
+        };
+        let victim = std::mem::replace(&mut ways[index], line);
+        if victim.prefetched {
+            self.stats.unused_prefetches += 1;
+        }
+        Some(victim)
+    }
+}
+
+/// A cache with prefetching compared against the same cache without it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CacheReport {
+    pub baseline: CacheStats,
+    pub prefetching: CacheStats,
+    pub replay: ReplayReport,
+}
+
+impl CacheReport {
+    /// Fraction of the baseline's demand misses removed by prefetching.
+    pub fn miss_reduction(&self) -> f64 {
+        if self.baseline.misses > 0 {
+            1.0 - self.prefetching.misses as f64 / self.baseline.misses as f64
+        } else {
+            0.0
+        }
+    }
+
+    pub fn baseline_mpki(&self) -> f64 {
+        self.baseline.mpki(self.replay.instructions)
+    }
+
+    pub fn prefetching_mpki(&self) -> f64 {
+        self.prefetching.mpki(self.replay.instructions)
+    }
+}
+
+/// Replay `records` through `prefetcher` and two caches with the given
+/// configuration: a baseline that only sees demand accesses, and one that
+/// also receives every predicted address as a prefetch.
+///
+/// Predictions are at the granularity chosen by `options` and are scaled
+/// back to byte addresses before being prefetched.
+pub fn simulate<A: Address>(
+    prefetcher: &mut PredictivePrefetcher<A>,
+    records: impl IntoIterator<Item = Result<TraceRecord, TraceError>>,
+    config: CacheConfig,
+    options: ReplayOptions,
+) -> Result<CacheReport, TraceError> {
+    let mut baseline = Cache::new(config);
+    let mut cache = Cache::new(config);
+    let replay = replay_each(prefetcher, records, options, |record, predictions| {
+        baseline.access(record.address);
+        cache.access(record.address);
+        for prediction in predictions {
+            cache.prefetch(prediction.to_bits().wrapping_mul(options.line_size.max(1)));
+        }
+    })?;
+
+    Ok(CacheReport {
+        baseline: baseline.stats(),
+        prefetching: cache.stats(),
+        replay,
+    })
+}
@@ -0,0 +1,207 @@
+use std::fmt;
+
+/// Tuning parameters of `PredictivePrefetcher`.
+///
+/// Start from `PrefetcherConfig::default()` and adjust it with the `with_*`
+/// methods; `PredictivePrefetcher::from_config` rejects invalid values with a
+/// `ConfigError`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PrefetcherConfig {
+    /// Accesses kept per stream for pattern detection.
+    pub history_size: usize,
+    /// Confidence a pattern needs for its predictions to be issued: the
+    /// predictor's own confidence in its candidates, and for a known trigger
+    /// also the confidence learned from feedback. The fallback guess of an
+    /// unrecognised pattern has no confidence, so it is only issued at 0.
+    pub min_confidence: f64,
+    /// Confidence of a newly detected pattern; at least `min_confidence`.
+    pub initial_confidence: f64,
+    /// Weight of each prefetch outcome in a pattern's confidence. A hit moves
+    /// the confidence this far towards 1, a miss this far towards 0.
+    pub learning_rate: f64,
+    /// Prefetch degree: predictions a new pattern type in a stream issues
+    /// per access, and the fewest it shrinks to.
+    pub min_window_size: usize,
+    /// Most predictions a pattern type in a stream issues per access.
+    pub max_window_size: usize,
+    /// A hit grows the degree once confidence is above this.
+    pub grow_threshold: f64,
+    /// A useless prefetch shrinks the degree while confidence is below this.
+    pub shrink_threshold: f64,
+    /// Prefetch distance: steps a new pattern type in a stream skips ahead
+    /// of the current access before its first prediction.
+    pub initial_distance: usize,
+    /// Furthest a pattern looks ahead. Late prefetches increase the distance
+    /// up to this, useless ones decrease it.
+    pub max_distance: usize,
+    /// Fraction of unit deltas in the history for a sequential pattern.
+    pub sequential_ratio: f64,
+    /// Fraction of the history's deltas the dominant stride must cover.
+    pub stride_ratio: f64,
+    /// Fraction of the history a cycle must repeat, exclusive.
+    pub repeat_ratio: f64,
+    /// Addresses per region of the spatial predictor, which learns the
+    /// footprint of offsets touched within each region. At most
+    /// `MAX_REGION_SIZE`, as every footprint is a bitmap of the region.
+    pub region_size: usize,
+}
+
+impl Default for PrefetcherConfig {
+    fn default() -> Self {
+        PrefetcherConfig {
+            history_size: 8,
+            min_confidence: 0.2,
+            initial_confidence: 0.5,
+            learning_rate: 0.2,
+            min_window_size: 2,
+            max_window_size: 4,
+            grow_threshold: 0.5,
+            shrink_threshold: 1.0,
+            initial_distance: 0,
+            max_distance: 0,
+            sequential_ratio: 0.5,
+            stride_ratio: 0.5,
+            repeat_ratio: 0.5,
+            region_size: 64,
+        }
+    }
+}
+
+/// An invalid `PrefetcherConfig` value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// `history_size` is zero.
+    EmptyHistory,
+    /// `region_size` is zero or larger than `PrefetcherConfig::MAX_REGION_SIZE`.
+    RegionSize { size: usize, max: usize },
+    /// `min_window_size` is zero or larger than `max_window_size`.
+    WindowSize { min: usize, max: usize },
+    /// `initial_distance` is larger than `max_distance`.
+    Distance { initial: usize, max: usize },
+    /// `initial_confidence` is below `min_confidence`.
+    Confidence { initial: f64, min: f64 },
+    /// A confidence, rate or ratio is not a number between 0 and 1.
+    OutOfRange { field: &'static str, value: f64 },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::EmptyHistory => write!(f, "history_size must be at least 1"),
+            ConfigError::RegionSize { size, max } => {
+                write!(f, "region_size must be between 1 and {}, got {}", max, size)
+            }
+            ConfigError::WindowSize { min, max } => write!(
+                f,
+                "window sizes must satisfy 1 <= min_window_size <= max_window_size, got {} and {}",
+                min, max
+            ),
+            ConfigError::Distance { initial, max } => write!(
+                f,
+                "initial_distance must not exceed max_distance, got {} and {}",
+                initial, max
+            ),
+            ConfigError::Confidence { initial, min } => write!(
+                f,
+                "initial_confidence must be at least min_confidence, got {} and {}",
+                initial, min
+            ),
+            ConfigError::OutOfRange { field, value } => write!(f, "{} must be between 0 and 1, got {}", field, value),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl PrefetcherConfig {
+    /// Largest `region_size`: a 256 KiB region of 64-byte lines.
+    pub const MAX_REGION_SIZE: usize = 4096;
+
+    pub fn with_history_size(mut self, history_size: usize) -> Self {
+        self.history_size = history_size;
+        self
+    }
+
+    pub fn with_min_confidence(mut self, min_confidence: f64) -> Self {
+        self.min_confidence = min_confidence;
+        self
+    }
+
+    pub fn with_initial_confidence(mut self, initial_confidence: f64) -> Self {
+        self.initial_confidence = initial_confidence;
+        self
+    }
+
+    pub fn with_learning_rate(mut self, learning_rate: f64) -> Self {
+        self.learning_rate = learning_rate;
+        self
+    }
+
+    pub fn with_window_size(mut self, min_window_size: usize, max_window_size: usize) -> Self {
+        self.min_window_size = min_window_size;
+        self.max_window_size = max_window_size;
+        self
+    }
+
+    pub fn with_distance(mut self, initial_distance: usize, max_distance: usize) -> Self {
+        self.initial_distance = initial_distance;
+        self.max_distance = max_distance;
+        self
+    }
+
+    pub fn with_window_thresholds(mut self, grow_threshold: f64, shrink_threshold: f64) -> Self {
+        self.grow_threshold = grow_threshold;
+        self.shrink_threshold = shrink_threshold;
+        self
+    }
+
+    pub fn with_detection_ratios(mut self, sequential: f64, stride: f64, repeat: f64) -> Self {
+        self.sequential_ratio = sequential;
+        self.stride_ratio = stride;
+        self.repeat_ratio = repeat;
+        self
+    }
+
+    pub fn with_region_size(mut self, region_size: usize) -> Self {
+        self.region_size = region_size;
+        self
+    }
+
+    /// Check every field, returning the first invalid one.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.history_size == 0 {
+            return Err(ConfigError::EmptyHistory);
+        }
+        if self.region_size == 0 || self.region_size > Self::MAX_REGION_SIZE {
+            return Err(ConfigError::RegionSize { size: self.region_size, max: Self::MAX_REGION_SIZE });
+        }
+        if self.min_window_size == 0 || self.min_window_size > self.max_window_size {
+            return Err(ConfigError::WindowSize { min: self.min_window_size, max: self.max_window_size });
+        }
+        if self.initial_distance > self.max_distance {
+            return Err(ConfigError::Distance { initial: self.initial_distance, max: self.max_distance });
+        }
+
+        let fractions = [
+            ("min_confidence", self.min_confidence),
+            ("initial_confidence", self.initial_confidence),
+            ("learning_rate", self.learning_rate),
+            ("grow_threshold", self.grow_threshold),
+            ("shrink_threshold", self.shrink_threshold),
+            ("sequential_ratio", self.sequential_ratio),
+            ("stride_ratio", self.stride_ratio),
+            ("repeat_ratio", self.repeat_ratio),
+        ];
+        for (field, value) in fractions {
+            // Also rejects NaN
+            if !(0.0..=1.0).contains(&value) {
+                return Err(ConfigError::OutOfRange { field, value });
+            }
+        }
+        if self.initial_confidence < self.min_confidence {
+            return Err(ConfigError::Confidence { initial: self.initial_confidence, min: self.min_confidence });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+use futures_core::Stream;
+use tokio::sync::Notify;
+
+use crate::address::Address;
+use crate::prefetcher::PredictionBatch;
+
+/// What happens to a prediction batch when a subscriber's channel is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DeliveryPolicy {
+    /// Wait in `access` until the consumer makes room. `observe` never
+    /// waits and drops the new batch instead.
+    #[default]
+    Block,
+    /// Drop the new batch.
+    DropNewest,
+    /// Drop the oldest queued batch to make room for the new one.
+    DropOldest,
+    /// Replace the latest queued batch of the same stream with the new one,
+    /// or drop the oldest batch if the stream has none queued.
+    Coalesce,
+}
+
+struct Inner<A: Address> {
+    queue: VecDeque<PredictionBatch<A>>,
+    waker: Option<Waker>,
+    sender_closed: bool,
+}
+
+// The channel of a single subscriber
+struct Shared<A: Address> {
+    inner: Mutex<Inner<A>>,
+    capacity: usize,
+    // Signalled when a batch is taken or the receiver goes away
+    space: Notify,
+    receiver_closed: AtomicBool,
+}
+
+impl<A: Address> Shared<A> {
+    fn inner(&self) -> MutexGuard<'_, Inner<A>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn is_full(&self) -> bool {
+        self.inner().queue.len() >= self.capacity
+    }
+
+    /// Queue `batch`, applying `policy` if the channel is full. Returns
+    /// `false` if a batch, the new one or a queued one, was dropped.
+    fn push(&self, batch: PredictionBatch<A>, policy: DeliveryPolicy) -> bool {
+        let mut inner = self.inner();
+        let mut delivered = true;
+        if inner.queue.len() >= self.capacity {
+            match policy {
+                DeliveryPolicy::Block | DeliveryPolicy::DropNewest => return false,
+                DeliveryPolicy::DropOldest => {
+                    inner.queue.pop_front();
+                }
+                DeliveryPolicy::Coalesce => {
+                    match inner.queue.iter().rposition(|queued| queued.stream_id == batch.stream_id) {
+                        Some(index) => inner.queue.remove(index),
+                        None => inner.queue.pop_front(),
+                    };
+                }
+            }
+            delivered = false;
+        }
+        inner.queue.push_back(batch);
+        let waker = inner.waker.take();
+        drop(inner);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        delivered
+    }
+}
+
+/// Broadcasts prediction batches to any number of subscribers, each with
+/// its own bounded channel.
+pub(crate) struct PredictionSender<A: Address> {
+    subscribers: Vec<Arc<Shared<A>>>,
+    policy: DeliveryPolicy,
+    capacity: usize,
+}
+
+/// A subscription to the prediction batches of a prefetcher, returned by
+/// `subscribe`. Every subscriber receives every batch, subject to the
+/// delivery policy. Also usable as a `futures::Stream`.
+///
+/// `recv` returns `None` once the prefetcher is dropped and every queued
+/// batch was taken.
+pub struct PredictionReceiver<A: Address = u64> {
+    shared: Arc<Shared<A>>,
+}
+
+impl<A: Address> PredictionSender<A> {
+    pub fn new(policy: DeliveryPolicy, capacity: usize) -> Self {
+        PredictionSender {
+            subscribers: Vec::new(),
+            policy,
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn subscribe(&mut self) -> PredictionReceiver<A> {
+        let shared = Arc::new(Shared {
+            inner: Mutex::new(Inner {
+                queue: VecDeque::new(),
+                waker: None,
+                sender_closed: false,
+            }),
+            capacity: self.capacity,
+            space: Notify::new(),
+            receiver_closed: AtomicBool::new(false),
+        });
+        self.subscribers.push(Arc::clone(&shared));
+        PredictionReceiver { shared }
+    }
+
+    /// Forget subscribers whose receiver was dropped.
+    fn prune(&mut self) {
+        self.subscribers.retain(|shared| !shared.receiver_closed.load(Ordering::Acquire));
+    }
+
+    pub fn has_subscribers(&mut self) -> bool {
+        self.prune();
+        !self.subscribers.is_empty()
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.iter()
+            .filter(|shared| !shared.receiver_closed.load(Ordering::Acquire))
+            .count()
+    }
+
+    /// Queue `batch` for every subscriber without waiting. Returns the
+    /// number of subscribers that dropped a batch.
+    pub fn try_send(&mut self, batch: PredictionBatch<A>) -> u64 {
+        self.prune();
+        let policy = self.policy;
+        self.subscribers.iter()
+            .filter(|shared| !shared.push(batch.clone(), policy))
+            .count() as u64
+    }
+
+    /// Queue `batch` for every subscriber, first waiting until all of them
+    /// have room under `DeliveryPolicy::Block`.
+    pub async fn send(&mut self, batch: PredictionBatch<A>) -> u64 {
+        if self.policy == DeliveryPolicy::Block {
+            loop {
+                self.prune();
+                let Some(full) = self.subscribers.iter().find(|shared| shared.is_full()).cloned() else {
+                    break;
+                };
+                full.space.notified().await;
+            }
+        }
+        self.try_send(batch)
+    }
+}
+
+impl<A: Address> Drop for PredictionSender<A> {
+    fn drop(&mut self) {
+        for shared in &self.subscribers {
+            let mut inner = shared.inner();
+            inner.sender_closed = true;
+            let waker = inner.waker.take();
+            drop(inner);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<A: Address> PredictionReceiver<A> {
+    /// Wait for the next batch.
+    pub async fn recv(&mut self) -> Option<PredictionBatch<A>> {
+        std::future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// Take the next batch if one is queued.
+    pub fn try_recv(&mut self) -> Option<PredictionBatch<A>> {
+        let batch = self.shared.inner().queue.pop_front();
+        if batch.is_some() {
+            self.shared.space.notify_one();
+        }
+        batch
+    }
+
+    /// Poll for the next batch, registering the task to be woken when one
+    /// arrives.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<PredictionBatch<A>>> {
+        let mut inner = self.shared.inner();
+        if let Some(batch) = inner.queue.pop_front() {
+            drop(inner);
+            self.shared.space.notify_one();
+            return Poll::Ready(Some(batch));
+        }
+        if inner.sender_closed {
+            return Poll::Ready(None);
+        }
+        inner.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Number of batches waiting to be received.
+    pub fn len(&self) -> usize {
+        self.shared.inner().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<A: Address> Stream for PredictionReceiver<A> {
+    type Item = PredictionBatch<A>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+impl<A: Address> Drop for PredictionReceiver<A> {
+    fn drop(&mut self) {
+        self.shared.receiver_closed.store(true, Ordering::Release);
+        self.shared.space.notify_one();
+    }
+}
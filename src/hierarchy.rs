@@ -0,0 +1,272 @@
+//! Multi-level cache hierarchy with a latency and bandwidth model, for
+//! judging whether prefetches arrive in time.
+//!
+//! Demand accesses look up each level in turn and pay the latency of the
+//! level that hits, or the memory latency plus any time spent waiting for
+//! the memory bus. Prefetches are installed into a single target level once
+//! their data arrives; a demand access to a line that is still in flight
+//! waits for it and counts as a late prefetch. Accesses are timed by the
+//! trace, so stalls do not delay later accesses.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::address::Address;
+use crate::cache::{Cache, CacheConfig, CacheStats};
+use crate::prefetcher::PredictivePrefetcher;
+use crate::trace::{replay_each, ReplayOptions, ReplayReport, TraceError, TraceRecord};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LevelConfig {
+    pub cache: CacheConfig,
+    /// Load-to-use latency of a hit in this level, in cycles.
+    pub latency: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MemoryConfig {
+    /// Latency of a memory access once it has the bus, in cycles.
+    pub latency: u64,
+    /// Cycles the bus is busy per transferred line, so one line every
+    /// `cycles_per_line` cycles is the peak bandwidth.
+    pub cycles_per_line: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HierarchyConfig {
+    /// Cache levels, closest to the core first.
+    pub levels: Vec<LevelConfig>,
+    pub memory: MemoryConfig,
+    /// Index of the level prefetched lines are installed into.
+    pub prefetch_level: usize,
+    /// Cycles per trace timestamp.
+    pub cycles_per_instruction: u64,
+}
+
+impl Default for HierarchyConfig {
+    /// 32 KiB L1, 256 KiB L2 and 2 MiB LLC at 4, 12 and 40 cycles, 200
+    /// cycle memory moving a line every 4 cycles, prefetching into L1.
+    fn default() -> Self {
+        let level = |size, associativity, latency| LevelConfig {
+            cache: CacheConfig { size, associativity, ..CacheConfig::default() },
+            latency,
+        };
+        HierarchyConfig {
+            levels: vec![level(32 * 1024, 8, 4), level(256 * 1024, 8, 12), level(2048 * 1024, 16, 40)],
+            memory: MemoryConfig { latency: 200, cycles_per_line: 4 },
+            prefetch_level: 0,
+            cycles_per_instruction: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HierarchyStats {
+    /// Demand accesses.
+    pub accesses: u64,
+    /// Cycles spent by all demand accesses.
+    pub total_latency: u64,
+    /// Demand hits of each level.
+    pub level_hits: Vec<u64>,
+    /// Demand accesses served by memory.
+    pub memory_accesses: u64,
+    pub prefetches_issued: u64,
+    /// Prefetched lines used by a demand access after they arrived.
+    pub timely_prefetches: u64,
+    /// Demand accesses that had to wait for a prefetch in flight.
+    pub late_prefetches: u64,
+    /// Statistics of each level's cache.
+    pub levels: Vec<CacheStats>,
+}
+
+impl HierarchyStats {
+    /// Average memory access time in cycles.
+    pub fn amat(&self) -> f64 {
+        if self.accesses > 0 {
+            self.total_latency as f64 / self.accesses as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+pub struct Hierarchy {
+    config: HierarchyConfig,
+    levels: Vec<Cache>,
+    // Lines of the prefetch level in flight and when they arrive
+    in_flight: HashMap<u64, u64>,
+    arrivals: BinaryHeap<Reverse<(u64, u64)>>,
+    bus_free: u64,
+    stats: HierarchyStats,
+}
+
+impl Hierarchy {
+    pub fn new(config: HierarchyConfig) -> Self {
+        Hierarchy {
+            levels: config.levels.iter().map(|level| Cache::new(level.cache)).collect(),
+            in_flight: HashMap::new(),
+            arrivals: BinaryHeap::new(),
+            bus_free: 0,
+            stats: HierarchyStats {
+                level_hits: vec![0; config.levels.len()],
+                ..HierarchyStats::default()
+            },
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &HierarchyConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> HierarchyStats {
+        let mut stats = self.stats.clone();
+        stats.levels = self.levels.iter().map(Cache::stats).collect();
+        stats.timely_prefetches = stats.levels.get(self.prefetch_level()).map_or(0, |level| level.useful_prefetches);
+        stats
+    }
+
+    fn prefetch_level(&self) -> usize {
+        self.config.prefetch_level.min(self.levels.len().saturating_sub(1))
+    }
+
+    fn line_size(&self) -> u64 {
+        self.levels.get(self.prefetch_level()).map_or(64, |level| level.config().line_size.max(1))
+    }
+
+    /// Reserve the memory bus for one line at `now`. Returns the cycle the
+    /// data arrives.
+    fn fetch_from_memory(&mut self, now: u64) -> u64 {
+        let start = now.max(self.bus_free);
+        self.bus_free = start + self.config.memory.cycles_per_line;
+        start + self.config.memory.latency
+    }
+
+    /// Install prefetched lines that have arrived by `now`.
+    fn drain_arrivals(&mut self, now: u64) {
+        let level = self.prefetch_level();
+        let line_size = self.line_size();
+        while let Some(&Reverse((arrival, line))) = self.arrivals.peek() {
+            if arrival > now {
+                break;
+            }
+            self.arrivals.pop();
+            // Skip lines a demand access already waited for
+            if self.in_flight.get(&line) == Some(&arrival) {
+                self.in_flight.remove(&line);
+                self.levels[level].prefetch(line * line_size);
+            }
+        }
+    }
+
+    /// Demand access to `address` at cycle `now`. Returns its latency.
+    pub fn access(&mut self, address: u64, now: u64) -> u64 {
+        self.drain_arrivals(now);
+        self.stats.accesses += 1;
+
+        let mut latency = None;
+        for (index, cache) in self.levels.iter_mut().enumerate() {
+            if cache.access(address) {
+                self.stats.level_hits[index] += 1;
+                latency = Some(self.config.levels[index].latency);
+                break;
+            }
+        }
+
+        let latency = latency.unwrap_or_else(|| {
+            let line = address / self.line_size();
+            match self.in_flight.remove(&line) {
+                Some(arrival) => {
+                    self.stats.late_prefetches += 1;
+                    let level = self.prefetch_level();
+                    arrival.saturating_sub(now) + self.config.levels[level].latency
+                }
+                None => {
+                    self.stats.memory_accesses += 1;
+                    self.fetch_from_memory(now) - now
+                }
+            }
+        });
+        self.stats.total_latency += latency;
+        latency
+    }
+
+    /// Prefetch the line holding `address` at cycle `now`. Returns `false`
+    /// if it is already cached in the prefetch level or in flight.
+    pub fn prefetch(&mut self, address: u64, now: u64) -> bool {
+        if self.levels.is_empty() {
+            return false;
+        }
+        let level = self.prefetch_level();
+        let line = address / self.line_size();
+        if self.levels[level].contains(address) || self.in_flight.contains_key(&line) {
+            return false;
+        }
+
+        let arrival = match (level + 1..self.levels.len()).find(|&i| self.levels[i].contains(address)) {
+            Some(source) => now + self.config.levels[source].latency,
+            None => self.fetch_from_memory(now),
+        };
+        self.in_flight.insert(line, arrival);
+        self.arrivals.push(Reverse((arrival, line)));
+        self.stats.prefetches_issued += 1;
+        true
+    }
+}
+
+/// The hierarchy with prefetching compared against the same hierarchy
+/// without it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HierarchyReport {
+    pub baseline: HierarchyStats,
+    pub prefetching: HierarchyStats,
+    pub replay: ReplayReport,
+}
+
+impl HierarchyReport {
+    pub fn baseline_amat(&self) -> f64 {
+        self.baseline.amat()
+    }
+
+    pub fn prefetching_amat(&self) -> f64 {
+        self.prefetching.amat()
+    }
+
+    /// Baseline AMAT over AMAT with prefetching.
+    pub fn speedup(&self) -> f64 {
+        if self.prefetching.amat() > 0.0 {
+            self.baseline.amat() / self.prefetching.amat()
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Replay `records` through `prefetcher` and two hierarchies: a baseline
+/// that only sees demand accesses and one that also prefetches every
+/// predicted address. Predictions are scaled back to byte addresses as in
+/// `cache::simulate`.
+pub fn simulate_hierarchy<A: Address>(
+    prefetcher: &mut PredictivePrefetcher<A>,
+    records: impl IntoIterator<Item = Result<TraceRecord, TraceError>>,
+    config: HierarchyConfig,
+    options: ReplayOptions,
+) -> Result<HierarchyReport, TraceError> {
+    let cycles_per_instruction = config.cycles_per_instruction;
+    let mut baseline = Hierarchy::new(config.clone());
+    let mut hierarchy = Hierarchy::new(config);
+    let replay = replay_each(prefetcher, records, options, |record, predictions| {
+        let now = record.timestamp.saturating_mul(cycles_per_instruction);
+        baseline.access(record.address, now);
+        hierarchy.access(record.address, now);
+        for prediction in predictions {
+            hierarchy.prefetch(prediction.to_bits().wrapping_mul(options.line_size.max(1)), now);
+        }
+    })?;
+
+    Ok(HierarchyReport {
+        baseline: baseline.stats(),
+        prefetching: hierarchy.stats(),
+        replay,
+    })
+}
@@ -4,35 +4,44 @@
 //! - Sequential patterns (1, 2, 3, 4...)
 //! - Strided patterns (2, 4, 6, 8...)
 //! - Repeated patterns (1, 2, 3, 1, 2, 3...)
+//! - Delta sequence patterns (0, 1, 2, 8, 9, 10, 16...)
+//! - Irregular but repeatable sequences, learned by a Markov chain
+//! - Recurring footprints of offsets within memory regions (spatial patterns)
+//!
+//! The prefetcher is generic over the [`Address`] type, so it can track
+//! 64-bit virtual addresses, file offsets or block numbers (`u32`, `u64`,
+//! `i64`, `usize`, as well as `i32`).
 //!
 //! # Example
 //!
 //! ```no_run
 //! use ml_prefetcher::PredictivePrefetcher;
 //!
-//! #[tokio::main]
-//! async fn main() {
-//!     // Create a new prefetcher
-//!     let mut prefetcher = PredictivePrefetcher::new(4);
-//!     
-//!     // Make predictions
-//!     println!("Sequential pattern test:");
-//!     for i in 1..=3 {
-//!         let predictions = prefetcher.access(i).await;
-//!         println!("Access: {}, Predicted next: {:?}", i, predictions);
-//!     }
-//!     
-//!     // Get accuracy stats
-//!     let (hits, misses, accuracy) = prefetcher.get_stats();
-//!     println!("Accuracy: {:.2}%", accuracy * 100.0);
+//! // Create a new prefetcher
+//! let mut prefetcher = PredictivePrefetcher::new(4);
+//!
+//! // Make predictions
+//! println!("Sequential pattern test:");
+//! for i in 1..=3 {
+//!     let predictions = prefetcher.access_sync(i);
+//!     println!("Access: {}, Predicted next: {:?}", i, predictions);
 //! }
+//!
+//! // Get accuracy stats
+//! let (hits, misses, accuracy) = prefetcher.get_stats();
+//! println!("Accuracy: {:.2}%", accuracy * 100.0);
 //! ```
 //!
-//! For advanced usage with custom settings:
+//! With the default `async` feature, predictions can also be awaited and
+//! streamed to another task:
 //!
 //! ```no_run
+//! # #[cfg(not(feature = "async"))]
+//! # fn main() {}
+//! # #[cfg(feature = "async")]
 //! use ml_prefetcher::PredictivePrefetcher;
 //!
+//! # #[cfg(feature = "async")]
 //! #[tokio::main]
 //! async fn main() {
 //!     let mut prefetcher = PredictivePrefetcher::with_config(
@@ -40,15 +49,56 @@
 //!         0.2,  // minimum confidence
 //!         4     // maximum window size
 //!     );
-//!     
+//!     let mut rx = prefetcher.start_async_predictor().await;
+//!     tokio::spawn(async move {
+//!         while let Some(batch) = rx.recv().await {
+//!             println!("{:?}", batch);
+//!         }
+//!     });
+//!
 //!     // Use with async/await
 //!     let predictions = prefetcher.access(1).await;
 //!     println!("Access: 1, Predicted: {:?}", predictions);
 //! }
 //! ```
 
+mod address;
+mod best_offset;
+pub mod cache;
+mod config;
+pub mod hierarchy;
+#[cfg(feature = "async")]
+mod delivery;
+mod markov;
+mod outstanding;
+mod pattern_table;
+mod perceptron;
+mod predictor;
 mod prefetcher;
+mod shared;
+mod spatial;
+#[cfg(feature = "serde")]
+mod snapshot;
+mod stats;
+pub mod trace;
 
+pub use address::Address;
+pub use best_offset::BestOffsetPredictor;
+pub use config::{ConfigError, PrefetcherConfig};
+#[cfg(feature = "async")]
+pub use delivery::{DeliveryPolicy, PredictionReceiver};
+pub use markov::{MarkovMode, MarkovPredictor};
+pub use pattern_table::EvictionPolicy;
+pub use predictor::{
+    default_predictors, AccessContext, Candidate, DeltaSequencePredictor, MarkovChainPredictor,
+    Predictor, RepeatedPredictor, SelectionMode, SequentialPredictor, StridedPredictor,
+};
 pub use prefetcher::PredictivePrefetcher;
+pub use prefetcher::DEFAULT_STREAM;
 pub use prefetcher::PatternType;
-pub use prefetcher::PredictionBatch;
\ No newline at end of file
+pub use prefetcher::PredictionBatch;
+pub use shared::SharedPrefetcher;
+pub use spatial::SpatialPredictor;
+#[cfg(feature = "serde")]
+pub use snapshot::{SnapshotError, SnapshotFormat, SNAPSHOT_VERSION};
+pub use stats::{PatternStats, PrefetchStats};
\ No newline at end of file
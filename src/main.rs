@@ -1,51 +1,445 @@
-use ml_prefetcher::PredictivePrefetcher;
-
-#[tokio::main]
-async fn main() {
-    let mut prefetcher = PredictivePrefetcher::new(4);
-    
-    // Start async predictor
-    let mut rx = prefetcher.start_async_predictor().await;
-    
-    // Spawn task to handle predictions
-    let prediction_handler = tokio::spawn(async move {
-        while let Some(batch) = rx.recv().await {
-            println!("Pattern {:?} ({:.2} confidence): {} -> {:?}", 
-                    batch.pattern_type, batch.confidence, 
-                    batch.address, batch.predictions);
-        }
-    });
-
-    // Test sequential pattern
-    println!("\nTesting sequential access pattern:");
-    for i in 1..10 {
-        let predictions = prefetcher.access(i).await;
-        println!("Accessed: {}, Prefetched: {:?}", i, predictions);
-    }
-
-    // Test strided pattern
-    println!("\nTesting strided access pattern:");
-    for i in (0..20).step_by(2) {
-        let predictions = prefetcher.access(i).await;
-        println!("Accessed: {}, Prefetched: {:?}", i, predictions);
-    }
-
-    // Test repeated pattern
-    println!("\nTesting pattern with repetitions:");
-    let pattern = vec![1, 2, 3, 1, 2, 3, 4, 5, 1, 2, 3];
-    for &i in &pattern {
-        let predictions = prefetcher.access(i).await;
-        println!("Accessed: {}, Prefetched: {:?}", i, predictions);
-    }
-
-    // Print final stats
-    let (hits, misses, accuracy) = prefetcher.get_stats();
-    println!("\nFinal Stats:");
-    println!("Hits: {}", hits);
-    println!("Misses: {}", misses);
-    println!("Accuracy: {:.2}%", accuracy * 100.0);
-
-    // Clean up
-    drop(prefetcher);
-    let _ = prediction_handler.await;
-}
\ No newline at end of file
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use rayon::prelude::*;
+
+use ml_prefetcher::cache::{simulate, CacheConfig, CacheReport, Replacement};
+use ml_prefetcher::trace::{analyze, Partition, PartProfile, ReplayOptions, TraceFormat, TraceReader};
+use ml_prefetcher::{BestOffsetPredictor, PredictivePrefetcher, PrefetcherConfig};
+
+const USAGE: &str = "\
+Usage: ml-prefetcher <command> <trace> [options]
+
+Commands:
+  simulate <trace>        Replay a trace and print prefetch and cache statistics
+  compare <trace>         Replay a trace once per --config and compare the results
+  sweep <trace>           Replay a trace for every combination of the listed values
+  analyze <trace>         Classify the access pattern of every stream (or region) in parallel
+
+Trace options:
+  --format <format>       text, csv, binary, champsim or lackey (default: from the extension)
+  --line-size <bytes>     Cache line size; accesses are replayed as line numbers (default: 64)
+  --no-pc                 Replay all accesses as one stream instead of one per PC
+  --region <bytes>        For analyze, split the trace into regions of this size instead of streams
+
+Cache options:
+  --cache-size <bytes>    Cache capacity (default: 32768)
+  --associativity <ways>  Cache associativity (default: 8)
+  --random                Random instead of LRU replacement
+
+Prefetcher options (sweep accepts comma-separated lists):
+  --history <n>           History size (default: 8)
+  --min-confidence <f>    Minimum confidence (default: 0.2)
+  --window <n>            Maximum window size (default: 4)
+  --predictors <set>      default (built-in detectors) or best-offset (default: default)
+  --config <spec>         For compare, repeatable: history=8,min-confidence=0.2,window=4,predictors=default
+  --max-distance <n>      Let late prefetches push the prefetch distance up to n (default: 0)
+
+Output:
+  --json                  Print JSON instead of a table
+";
+
+/// The pattern detectors a run uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum PredictorSet {
+    #[default]
+    Default,
+    BestOffset,
+}
+
+impl PredictorSet {
+    fn name(self) -> &'static str {
+        match self {
+            PredictorSet::Default => "default",
+            PredictorSet::BestOffset => "best-offset",
+        }
+    }
+}
+
+impl std::str::FromStr for PredictorSet {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, ()> {
+        match value {
+            "default" => Ok(PredictorSet::Default),
+            "best-offset" => Ok(PredictorSet::BestOffset),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RunConfig {
+    history_size: usize,
+    min_confidence: f64,
+    max_window_size: usize,
+    predictors: PredictorSet,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        RunConfig { history_size: 8, min_confidence: 0.2, max_window_size: 4, predictors: PredictorSet::Default }
+    }
+}
+
+impl RunConfig {
+    fn label(&self) -> String {
+        let mut label = format!(
+            "history={},min-confidence={},window={}",
+            self.history_size, self.min_confidence, self.max_window_size
+        );
+        if self.predictors != PredictorSet::Default {
+            label.push_str(&format!(",predictors={}", self.predictors.name()));
+        }
+        label
+    }
+
+    /// Parse a `--config` spec, starting from `base` for omitted keys.
+    fn parse(spec: &str, base: RunConfig) -> Result<RunConfig, String> {
+        let mut config = base;
+        for pair in spec.split(',').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').ok_or_else(|| format!("invalid config entry {:?}", pair))?;
+            match key.trim() {
+                "history" => config.history_size = parse_value(key, value)?,
+                "min-confidence" => config.min_confidence = parse_value(key, value)?,
+                "window" => config.max_window_size = parse_value(key, value)?,
+                "predictors" => config.predictors = parse_value(key, value)?,
+                _ => return Err(format!("unknown config key {:?}", key)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Simulate,
+    Compare,
+    Sweep,
+    Analyze,
+}
+
+#[derive(Debug)]
+struct Args {
+    command: Command,
+    trace: PathBuf,
+    format: Option<TraceFormat>,
+    line_size: u64,
+    per_pc: bool,
+    region: Option<u64>,
+    cache: CacheConfig,
+    history: Vec<usize>,
+    min_confidence: Vec<f64>,
+    window: Vec<usize>,
+    predictors: Vec<PredictorSet>,
+    configs: Vec<String>,
+    max_distance: usize,
+    json: bool,
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.trim().parse().map_err(|_| format!("invalid value {:?} for {}", value, name))
+}
+
+fn parse_list<T: std::str::FromStr>(name: &str, value: &str) -> Result<Vec<T>, String> {
+    value.split(',').map(|item| parse_value(name, item)).collect()
+}
+
+fn parse_format(value: &str) -> Result<TraceFormat, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "text" => Ok(TraceFormat::Text),
+        "csv" => Ok(TraceFormat::Csv),
+        "binary" => Ok(TraceFormat::Binary),
+        "champsim" => Ok(TraceFormat::ChampSim),
+        "lackey" => Ok(TraceFormat::Lackey),
+        _ => Err(format!("unknown trace format {:?}", value)),
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Args, String> {
+    let mut args = args.into_iter();
+    let command = match args.next().as_deref() {
+        Some("simulate") => Command::Simulate,
+        Some("compare") => Command::Compare,
+        Some("sweep") => Command::Sweep,
+        Some("analyze") => Command::Analyze,
+        Some(other) => return Err(format!("unknown command {:?}", other)),
+        None => return Err("missing command".to_string()),
+    };
+
+    let mut parsed = Args {
+        command,
+        trace: PathBuf::new(),
+        format: None,
+        line_size: 64,
+        per_pc: true,
+        region: None,
+        cache: CacheConfig::default(),
+        history: Vec::new(),
+        min_confidence: Vec::new(),
+        window: Vec::new(),
+        predictors: Vec::new(),
+        configs: Vec::new(),
+        max_distance: 0,
+        json: false,
+    };
+    let mut trace = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--format" => parsed.format = Some(parse_format(&value()?)?),
+            "--line-size" => parsed.line_size = parse_value(&arg, &value()?)?,
+            "--no-pc" => parsed.per_pc = false,
+            "--region" => parsed.region = Some(parse_value(&arg, &value()?)?),
+            "--cache-size" => parsed.cache.size = parse_value(&arg, &value()?)?,
+            "--associativity" => parsed.cache.associativity = parse_value(&arg, &value()?)?,
+            "--random" => parsed.cache.replacement = Replacement::Random,
+            "--history" => parsed.history = parse_list(&arg, &value()?)?,
+            "--min-confidence" => parsed.min_confidence = parse_list(&arg, &value()?)?,
+            "--window" => parsed.window = parse_list(&arg, &value()?)?,
+            "--predictors" => parsed.predictors = parse_list(&arg, &value()?)?,
+            "--config" => parsed.configs.push(value()?),
+            "--max-distance" => parsed.max_distance = parse_value(&arg, &value()?)?,
+            "--json" => parsed.json = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if trace.is_none() => trace = Some(PathBuf::from(&arg)),
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    parsed.trace = trace.ok_or("missing trace file")?;
+    if parsed.line_size == 0 {
+        return Err("--line-size must be positive".to_string());
+    }
+    if parsed.region.is_some() && parsed.command != Command::Analyze {
+        return Err("--region only applies to analyze".to_string());
+    }
+    if parsed.region == Some(0) {
+        return Err("--region must be positive".to_string());
+    }
+    parsed.cache.line_size = parsed.line_size;
+    Ok(parsed)
+}
+
+/// A prefetcher with a configuration already checked by `prefetcher_config`.
+fn build_prefetcher(config: PrefetcherConfig, predictors: PredictorSet) -> PredictivePrefetcher<u64> {
+    let prefetcher = PredictivePrefetcher::from_config(config).expect("configuration was validated");
+    match predictors {
+        PredictorSet::Default => prefetcher,
+        PredictorSet::BestOffset => prefetcher.with_predictors(vec![Box::new(BestOffsetPredictor::default())]),
+    }
+}
+
+impl Args {
+    /// The prefetcher configurations to run, in order.
+    fn run_configs(&self) -> Result<Vec<RunConfig>, String> {
+        let default = RunConfig::default();
+        let history = if self.history.is_empty() { vec![default.history_size] } else { self.history.clone() };
+        let min_confidence = if self.min_confidence.is_empty() { vec![default.min_confidence] } else { self.min_confidence.clone() };
+        let window = if self.window.is_empty() { vec![default.max_window_size] } else { self.window.clone() };
+        let predictors = if self.predictors.is_empty() { vec![default.predictors] } else { self.predictors.clone() };
+
+        let mut grid = Vec::new();
+        for &history_size in &history {
+            for &min_confidence in &min_confidence {
+                for &max_window_size in &window {
+                    for &predictors in &predictors {
+                        grid.push(RunConfig { history_size, min_confidence, max_window_size, predictors });
+                    }
+                }
+            }
+        }
+
+        match self.command {
+            Command::Sweep => Ok(grid),
+            _ if grid.len() > 1 => Err("only sweep accepts lists of values".to_string()),
+            Command::Simulate | Command::Analyze => Ok(grid),
+            Command::Compare if self.configs.is_empty() => Err("compare needs at least one --config".to_string()),
+            Command::Compare => self.configs.iter().map(|spec| RunConfig::parse(spec, grid[0])).collect(),
+        }
+    }
+
+    fn reader(&self) -> Result<TraceReader<std::io::BufReader<std::fs::File>>, String> {
+        let format = self.format.unwrap_or_else(|| TraceFormat::from_path(&self.trace));
+        TraceReader::open_with_format(&self.trace, format).map_err(|err| format!("{}: {}", self.trace.display(), err))
+    }
+
+    fn prefetcher_config(&self, config: RunConfig) -> Result<PrefetcherConfig, String> {
+        let default = PrefetcherConfig::default();
+        let prefetcher_config = default.clone()
+            .with_history_size(config.history_size)
+            .with_min_confidence(config.min_confidence)
+            .with_initial_confidence(default.initial_confidence.max(config.min_confidence))
+            .with_window_size(default.min_window_size.min(config.max_window_size), config.max_window_size)
+            .with_distance(0, self.max_distance);
+        prefetcher_config.validate().map_err(|err| format!("{}: {}", config.label(), err))?;
+        Ok(prefetcher_config)
+    }
+
+    fn options(&self) -> ReplayOptions {
+        ReplayOptions { line_size: self.line_size, per_pc: self.per_pc }
+    }
+
+    fn run(&self, config: RunConfig) -> Result<CacheReport, String> {
+        let reader = self.reader()?;
+        let mut prefetcher = build_prefetcher(self.prefetcher_config(config)?, config.predictors);
+        simulate(&mut prefetcher, reader, self.cache, self.options())
+            .map_err(|err| format!("{}: {}", self.trace.display(), err))
+    }
+
+    fn analyze(&self, config: RunConfig) -> Result<Vec<PartProfile>, String> {
+        let reader = self.reader()?;
+        let prefetcher_config = self.prefetcher_config(config)?;
+        let partition = self.region.map_or(Partition::Stream, Partition::Region);
+        let build = || build_prefetcher(prefetcher_config.clone(), config.predictors);
+        analyze(reader, self.options(), partition, build)
+            .map(|analysis| analysis.parts)
+            .map_err(|err| format!("{}: {}", self.trace.display(), err))
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn json_report(config: &RunConfig, report: &CacheReport) -> String {
+    let stats = &report.replay.stats;
+    format!(
+        concat!(
+            "{{\"config\":{},\"history_size\":{},\"min_confidence\":{},\"max_window_size\":{},\"predictors\":{},",
+            "\"records\":{},\"instructions\":{},\"predictions\":{},\"accuracy\":{},\"coverage\":{},",
+            "\"baseline_misses\":{},\"prefetching_misses\":{},\"miss_reduction\":{},",
+            "\"baseline_mpki\":{},\"prefetching_mpki\":{},\"prefetch_evictions\":{}}}"
+        ),
+        json_string(&config.label()),
+        config.history_size,
+        config.min_confidence,
+        config.max_window_size,
+        json_string(config.predictors.name()),
+        report.replay.records,
+        report.replay.instructions,
+        report.replay.predictions,
+        stats.accuracy(),
+        stats.coverage(),
+        report.baseline.misses,
+        report.prefetching.misses,
+        report.miss_reduction(),
+        report.baseline_mpki(),
+        report.prefetching_mpki(),
+        report.prefetching.prefetch_evictions,
+    )
+}
+
+fn json_part(part: &PartProfile) -> String {
+    let patterns: Vec<String> = part.patterns
+        .iter()
+        .map(|(pattern_type, accesses)| format!("{}:{}", json_string(&format!("{:?}", pattern_type)), accesses))
+        .collect();
+    format!(
+        "{{\"key\":{},\"accesses\":{},\"pattern\":{},\"patterns\":{{{}}},\"accuracy\":{},\"coverage\":{}}}",
+        part.key,
+        part.accesses,
+        json_string(&format!("{:?}", part.pattern_type)),
+        patterns.join(","),
+        part.stats.accuracy(),
+        part.stats.coverage(),
+    )
+}
+
+fn print_parts(parts: &[PartProfile], region: Option<u64>) {
+    let total: u64 = parts.iter().map(|part| part.accesses).sum();
+    let key = if region.is_some() { "region" } else { "stream" };
+    println!("{:>18}  {:>10}  {:>7}  {:<16}  {:>8}  {:>8}", key, "accesses", "share", "pattern", "accuracy", "coverage");
+    for part in parts {
+        let key = match region {
+            Some(size) => format!("{:#x}", part.key.saturating_mul(size)),
+            None => format!("{:#x}", part.key),
+        };
+        println!(
+            "{:>18}  {:>10}  {:>6.1}%  {:<16}  {:>8.3}  {:>8.3}",
+            key,
+            part.accesses,
+            part.accesses as f64 * 100.0 / total.max(1) as f64,
+            format!("{:?}", part.pattern_type),
+            part.stats.accuracy(),
+            part.stats.coverage(),
+        );
+    }
+}
+
+fn print_table(results: &[(RunConfig, CacheReport)]) {
+    let labels: Vec<String> = results.iter().map(|(config, _)| config.label()).collect();
+    let width = labels.iter().map(String::len).max().unwrap_or(0).max("config".len());
+    println!(
+        "{:<width$}  {:>10}  {:>8}  {:>8}  {:>9}  {:>10}  {:>10}  {:>9}",
+        "config", "records", "accuracy", "coverage", "miss red.", "base MPKI", "pf MPKI", "evictions",
+    );
+    for (label, (_, report)) in labels.iter().zip(results) {
+        println!(
+            "{:<width$}  {:>10}  {:>8.3}  {:>8.3}  {:>8.1}%  {:>10.2}  {:>10.2}  {:>9}",
+            label,
+            report.replay.records,
+            report.replay.stats.accuracy(),
+            report.replay.stats.coverage(),
+            report.miss_reduction() * 100.0,
+            report.baseline_mpki(),
+            report.prefetching_mpki(),
+            report.prefetching.prefetch_evictions,
+        );
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let args = parse_args(args)?;
+    if args.command == Command::Analyze {
+        let parts = args.analyze(args.run_configs()?[0])?;
+        if args.json {
+            let parts: Vec<String> = parts.iter().map(json_part).collect();
+            println!("[{}]", parts.join(","));
+        } else {
+            print_parts(&parts, args.region);
+        }
+        return Ok(());
+    }
+
+    // Every configuration replays the trace on its own, so run them in parallel
+    let results = args.run_configs()?
+        .into_par_iter()
+        .map(|config| args.run(config).map(|report| (config, report)))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if args.json {
+        let reports: Vec<String> = results.iter().map(|(config, report)| json_report(config, report)).collect();
+        match args.command {
+            Command::Simulate => println!("{}", reports[0]),
+            _ => println!("[{}]", reports.join(",")),
+        }
+    } else {
+        print_table(&results);
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        print!("{}", USAGE);
+        return;
+    }
+    if let Err(err) = run(args) {
+        eprintln!("error: {}\n\n{}", err, USAGE);
+        process::exit(2);
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::address::Address;
+
+const MAX_SUCCESSORS: usize = 8;
+
+/// What a Markov chain learns transitions between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MarkovMode {
+    /// Transitions between absolute addresses (hash probes, pointer chasing).
+    #[default]
+    Address,
+    /// Transitions between deltas, so a learned sequence also applies when
+    /// the same walk is repeated at a different base address.
+    Delta,
+}
+
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Successors {
+    counts: Vec<(u64, u32)>,
+    total: u32,
+}
+
+impl Successors {
+    fn record(&mut self, symbol: u64) {
+        self.total += 1;
+        if let Some(entry) = self.counts.iter_mut().find(|(s, _)| *s == symbol) {
+            entry.1 += 1;
+            return;
+        }
+        if self.counts.len() >= MAX_SUCCESSORS {
+            // Replace the least frequent successor
+            if let Some(pos) = self.counts.iter().enumerate().min_by_key(|(_, (_, c))| *c).map(|(i, _)| i) {
+                let (_, count) = self.counts.swap_remove(pos);
+                self.total -= count;
+            }
+        }
+        self.counts.push((symbol, 1));
+    }
+}
+
+/// Markov-chain predictor of configurable order.
+///
+/// The last `order` addresses (or deltas) form the context, and for every
+/// context the predictor counts which address (or delta) came next. Each
+/// context keeps at most eight successors and the number of contexts is
+/// bounded, oldest first.
+pub struct MarkovPredictor<A: Address> {
+    order: usize,
+    mode: MarkovMode,
+    max_contexts: usize,
+    recent: VecDeque<A>,
+    table: HashMap<Vec<u64>, Successors>,
+    insertion_order: VecDeque<Vec<u64>>,
+}
+
+impl<A: Address> MarkovPredictor<A> {
+    pub fn new(order: usize, mode: MarkovMode) -> Self {
+        Self::with_capacity(order, mode, 4096)
+    }
+
+    pub fn with_capacity(order: usize, mode: MarkovMode, max_contexts: usize) -> Self {
+        MarkovPredictor {
+            order: order.max(1),
+            mode,
+            max_contexts: max_contexts.max(1),
+            recent: VecDeque::new(),
+            table: HashMap::new(),
+            insertion_order: VecDeque::new(),
+        }
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn mode(&self) -> MarkovMode {
+        self.mode
+    }
+
+    fn symbols(&self) -> Vec<u64> {
+        match self.mode {
+            MarkovMode::Address => self.recent.iter().map(|a| a.to_bits()).collect(),
+            MarkovMode::Delta => {
+                let mut iter = self.recent.iter();
+                let mut deltas = Vec::with_capacity(self.recent.len().saturating_sub(1));
+                if let Some(mut prev) = iter.next().copied() {
+                    for &next in iter {
+                        deltas.push(next.delta(prev) as u64);
+                        prev = next;
+                    }
+                }
+                deltas
+            }
+        }
+    }
+
+    fn window(&self) -> usize {
+        match self.mode {
+            MarkovMode::Address => self.order + 1,
+            MarkovMode::Delta => self.order + 2,
+        }
+    }
+
+    /// Record the next access and learn the transition that led to it.
+    pub fn observe(&mut self, address: A) {
+        self.recent.push_back(address);
+        while self.recent.len() > self.window() {
+            self.recent.pop_front();
+        }
+        if self.recent.len() < self.window() {
+            return;
+        }
+
+        let mut symbols = self.symbols();
+        let successor = symbols.pop().unwrap_or_default();
+        if !self.table.contains_key(&symbols) {
+            if self.table.len() >= self.max_contexts {
+                if let Some(oldest) = self.insertion_order.pop_front() {
+                    self.table.remove(&oldest);
+                }
+            }
+            self.insertion_order.push_back(symbols.clone());
+        }
+        self.table.entry(symbols).or_default().record(successor);
+    }
+
+    /// The `k` most likely next addresses after the observed accesses,
+    /// most likely first, with their probabilities.
+    pub fn predict(&self, k: usize) -> Vec<(A, f64)> {
+        let Some(&current) = self.recent.back() else {
+            return Vec::new();
+        };
+        let symbols = self.symbols();
+        if symbols.len() < self.order {
+            return Vec::new();
+        }
+        let context = &symbols[symbols.len() - self.order..];
+        let Some(successors) = self.table.get(context) else {
+            return Vec::new();
+        };
+
+        let mut ranked: Vec<_> = successors.counts.iter().collect();
+        ranked.sort_by_key(|&&(_, count)| std::cmp::Reverse(count));
+        ranked
+            .into_iter()
+            .filter_map(|&(symbol, count)| {
+                let next = match self.mode {
+                    MarkovMode::Address => Some(A::from_bits(symbol)),
+                    MarkovMode::Delta => current.checked_offset(symbol as i64),
+                }?;
+                Some((next, count as f64 / successors.total as f64))
+            })
+            .take(k)
+            .collect()
+    }
+}
+
+/// Learned state of a `MarkovPredictor`, with addresses as bit patterns and
+/// contexts oldest first.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct MarkovState {
+    order: usize,
+    mode: MarkovMode,
+    max_contexts: usize,
+    recent: Vec<u64>,
+    contexts: Vec<(Vec<u64>, Successors)>,
+}
+
+#[cfg(feature = "serde")]
+impl<A: Address> MarkovPredictor<A> {
+    pub(crate) fn save(&self) -> MarkovState {
+        MarkovState {
+            order: self.order,
+            mode: self.mode,
+            max_contexts: self.max_contexts,
+            recent: self.recent.iter().map(|a| a.to_bits()).collect(),
+            contexts: self
+                .insertion_order
+                .iter()
+                .filter_map(|context| Some((context.clone(), self.table.get(context)?.clone())))
+                .collect(),
+        }
+    }
+
+    /// Rebuild a chain from `state`, or `None` if it was saved with another
+    /// order or mode.
+    pub(crate) fn restore(state: MarkovState, order: usize, mode: MarkovMode) -> Option<Self> {
+        if state.order != order.max(1) || state.mode != mode {
+            return None;
+        }
+        let mut chain = Self::with_capacity(state.order, state.mode, state.max_contexts);
+        chain.recent = state.recent.into_iter().map(A::from_bits).collect();
+        for (context, successors) in state.contexts {
+            chain.insertion_order.push_back(context.clone());
+            chain.table.insert(context, successors);
+        }
+        Some(chain)
+    }
+}
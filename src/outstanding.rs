@@ -0,0 +1,179 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::address::Address;
+use crate::prefetcher::PatternType;
+#[cfg(feature = "serde")]
+use crate::snapshot::OutstandingEntry;
+use crate::stats::PrefetchStats;
+
+/// The pattern-table entry, pattern and predictor whose prediction issued a
+/// prefetch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Issuer<A: Address> {
+    pub stream_id: u64,
+    pub trigger: A,
+    pub pattern_type: PatternType,
+    pub source: Option<usize>,
+}
+
+struct Outstanding<A: Address> {
+    issuer: Issuer<A>,
+    issued_at: u64,
+}
+
+/// Prefetches that have been issued but not yet consumed by a demand access.
+///
+/// Entries leave the buffer when they are consumed (useful), when they are
+/// older than the lifetime, or when the buffer is full and they are the oldest
+/// (useless). Time is measured in accesses. Consumed entries leave their slot
+/// in the issue order behind; the order is compacted once it holds twice the
+/// capacity.
+pub(crate) struct OutstandingPrefetches<A: Address> {
+    entries: HashMap<A, Outstanding<A>>,
+    order: VecDeque<(A, u64)>,
+    capacity: usize,
+    lifetime: u64,
+}
+
+impl<A: Address> OutstandingPrefetches<A> {
+    pub fn new(capacity: usize, lifetime: u64) -> Self {
+        OutstandingPrefetches {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            capacity: capacity.max(1),
+            lifetime,
+        }
+    }
+
+    /// Outstanding prefetches, oldest first.
+    #[cfg(feature = "serde")]
+    pub fn save(&self) -> Vec<OutstandingEntry> {
+        self.order
+            .iter()
+            .filter_map(|&(address, issued_at)| {
+                let prefetch = self.entries.get(&address).filter(|e| e.issued_at == issued_at)?;
+                Some(OutstandingEntry {
+                    address: address.to_bits(),
+                    stream_id: prefetch.issuer.stream_id,
+                    trigger: prefetch.issuer.trigger.to_bits(),
+                    pattern_type: prefetch.issuer.pattern_type.clone(),
+                    source: prefetch.issuer.source,
+                    issued_at,
+                })
+            })
+            .collect()
+    }
+
+    /// Replace the outstanding prefetches with saved ones, keeping only the
+    /// newest that fit the buffer.
+    #[cfg(feature = "serde")]
+    pub fn restore(&mut self, saved: Vec<OutstandingEntry>) {
+        self.entries.clear();
+        self.order.clear();
+        let skip = saved.len().saturating_sub(self.capacity);
+        for entry in saved.into_iter().skip(skip) {
+            let address = A::from_bits(entry.address);
+            let issuer = Issuer {
+                stream_id: entry.stream_id,
+                trigger: A::from_bits(entry.trigger),
+                pattern_type: entry.pattern_type,
+                source: entry.source,
+            };
+            self.entries.insert(address, Outstanding { issuer, issued_at: entry.issued_at });
+            self.order.push_back((address, entry.issued_at));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Pop the front queue slot, returning the prefetched address and its
+    // issuer if it still held an entry (slots of consumed prefetches are left
+    // behind and skipped here)
+    fn pop_front(&mut self, stats: &mut PrefetchStats) -> Option<(A, Issuer<A>)> {
+        let (address, issued_at) = self.order.pop_front()?;
+        if self.entries.get(&address).is_none_or(|e| e.issued_at != issued_at) {
+            return None;
+        }
+        let expired = self.entries.remove(&address)?;
+        stats.record_useless(&expired.issuer.pattern_type);
+        Some((address, expired.issuer))
+    }
+
+    /// Expire every prefetch that outlived its lifetime, returning the
+    /// addresses and issuers of the expired prefetches.
+    pub fn expire(&mut self, now: u64, stats: &mut PrefetchStats) -> Vec<(A, Issuer<A>)> {
+        let mut expired = Vec::new();
+        while let Some(&(_, issued_at)) = self.order.front() {
+            if now.saturating_sub(issued_at) <= self.lifetime {
+                break;
+            }
+            expired.extend(self.pop_front(stats));
+        }
+        expired
+    }
+
+    /// Record an issued prefetch. Addresses already outstanding are not
+    /// issued again. Returns the address and issuer of the prefetch displaced
+    /// to make room, if any.
+    pub fn issue(
+        &mut self,
+        address: A,
+        issuer: &Issuer<A>,
+        now: u64,
+        stats: &mut PrefetchStats,
+    ) -> Option<(A, Issuer<A>)> {
+        if self.entries.contains_key(&address) {
+            return None;
+        }
+        let mut displaced = None;
+        while displaced.is_none() && self.entries.len() >= self.capacity && !self.order.is_empty() {
+            displaced = self.pop_front(stats);
+        }
+        stats.record_issued(&issuer.pattern_type);
+        self.entries.insert(address, Outstanding { issuer: issuer.clone(), issued_at: now });
+        self.order.push_back((address, now));
+        if self.order.len() > 2 * self.capacity {
+            let entries = &self.entries;
+            self.order.retain(|(address, issued_at)| entries.get(address).is_some_and(|e| e.issued_at == *issued_at));
+        }
+        displaced
+    }
+
+    /// Consume an outstanding prefetch for a demand access, returning its
+    /// issuer and whether it was late: issued less than `min_lead` accesses
+    /// ago.
+    pub fn consume(
+        &mut self,
+        address: A,
+        now: u64,
+        min_lead: u64,
+        stats: &mut PrefetchStats,
+    ) -> Option<(Issuer<A>, bool)> {
+        let prefetch = self.entries.remove(&address)?;
+        let late = now.saturating_sub(prefetch.issued_at) < min_lead;
+        stats.record_useful(&prefetch.issuer.pattern_type, late);
+        Some((prefetch.issuer, late))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consumed_slots_are_compacted() {
+        // Without a lifetime or a full buffer nothing else pops the slots
+        let mut outstanding = OutstandingPrefetches::new(4, u64::MAX);
+        let mut stats = PrefetchStats::default();
+        let issuer = Issuer { stream_id: 0, trigger: 0u64, pattern_type: PatternType::Sequential, source: None };
+        for now in 0..10_000u64 {
+            outstanding.issue(now + 1, &issuer, now, &mut stats);
+            outstanding.consume(now + 1, now + 1, 0, &mut stats);
+            assert!(outstanding.order.len() <= 8);
+        }
+        assert_eq!(outstanding.len(), 0);
+        assert_eq!(stats.useful_prefetches, 10_000);
+    }
+}
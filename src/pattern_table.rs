@@ -0,0 +1,156 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::address::Address;
+use crate::prefetcher::AccessPattern;
+#[cfg(feature = "serde")]
+use crate::snapshot::{PatternEntry, PatternTableState};
+
+/// Replacement policy used when the pattern table is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EvictionPolicy {
+    /// Evict the pattern that was updated least recently.
+    #[default]
+    Lru,
+    /// Evict the pattern with the lowest access frequency.
+    Lfu,
+    /// Evict the pattern with the lowest confidence.
+    LowestConfidence,
+}
+
+type Key<A> = (u64, A);
+
+struct Entry {
+    pattern: AccessPattern,
+    rank: u64,
+    last_access: u64,
+}
+
+/// Pattern table with a fixed capacity.
+///
+/// Entries are kept in a `BTreeSet` ordered by the policy's rank (ties broken
+/// by recency), so finding the victim and re-ranking an updated entry are
+/// both logarithmic.
+pub(crate) struct PatternTable<A: Address> {
+    entries: HashMap<Key<A>, Entry>,
+    order: BTreeSet<(u64, u64, Key<A>)>,
+    capacity: usize,
+    policy: EvictionPolicy,
+    evictions: u64,
+    clock: u64,
+}
+
+impl<A: Address> PatternTable<A> {
+    pub fn new(capacity: usize, policy: EvictionPolicy) -> Self {
+        PatternTable {
+            entries: HashMap::new(),
+            order: BTreeSet::new(),
+            capacity: capacity.max(1),
+            policy,
+            evictions: 0,
+            clock: 0,
+        }
+    }
+
+    fn rank(&self, pattern: &AccessPattern, last_access: u64) -> u64 {
+        match self.policy {
+            EvictionPolicy::Lru => last_access,
+            EvictionPolicy::Lfu => pattern.frequency() as u64,
+            // Confidence is never negative, so its bit pattern sorts like the value
+            EvictionPolicy::LowestConfidence => pattern.confidence().max(0.0).to_bits(),
+        }
+    }
+
+    pub fn get(&self, key: &Key<A>) -> Option<&AccessPattern> {
+        self.entries.get(key).map(|entry| &entry.pattern)
+    }
+
+    /// Update the pattern stored under `key`, or insert `new_pattern` if
+    /// there is none, evicting a victim when the table is full.
+    pub fn update_or_insert(
+        &mut self,
+        key: Key<A>,
+        new_pattern: AccessPattern,
+        update: impl FnOnce(&mut AccessPattern),
+    ) {
+        if self.entries.contains_key(&key) {
+            self.update(key, update);
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            self.evict();
+        }
+        self.clock += 1;
+        let rank = self.rank(&new_pattern, self.clock);
+        self.order.insert((rank, self.clock, key));
+        self.entries.insert(key, Entry { pattern: new_pattern, rank, last_access: self.clock });
+    }
+
+    /// Update the pattern stored under `key`, if there is one.
+    pub fn update(&mut self, key: Key<A>, update: impl FnOnce(&mut AccessPattern)) {
+        let Some(mut entry) = self.entries.remove(&key) else {
+            return;
+        };
+        self.clock += 1;
+        self.order.remove(&(entry.rank, entry.last_access, key));
+        update(&mut entry.pattern);
+        entry.last_access = self.clock;
+        entry.rank = self.rank(&entry.pattern, entry.last_access);
+        self.order.insert((entry.rank, entry.last_access, key));
+        self.entries.insert(key, entry);
+    }
+
+    fn evict(&mut self) {
+        if let Some((_, _, victim)) = self.order.pop_first() {
+            self.entries.remove(&victim);
+            self.evictions += 1;
+        }
+    }
+
+    /// Drop every pattern learned for `stream_id`.
+    pub fn remove_stream(&mut self, stream_id: u64) {
+        self.entries.retain(|&(id, _), _| id != stream_id);
+        self.order.retain(|&(_, _, (id, _))| id != stream_id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn save(&self) -> PatternTableState {
+        PatternTableState {
+            capacity: self.capacity,
+            policy: self.policy,
+            evictions: self.evictions,
+            clock: self.clock,
+            entries: self.entries
+                .iter()
+                .map(|(&(stream_id, trigger), entry)| PatternEntry {
+                    stream_id,
+                    trigger: trigger.to_bits(),
+                    last_access: entry.last_access,
+                    pattern: entry.pattern.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn restore(state: PatternTableState) -> Self {
+        let mut table = PatternTable::new(state.capacity, state.policy);
+        table.evictions = state.evictions;
+        table.clock = state.clock;
+        for entry in state.entries {
+            let key = (entry.stream_id, A::from_bits(entry.trigger));
+            let rank = table.rank(&entry.pattern, entry.last_access);
+            table.order.insert((rank, entry.last_access, key));
+            table.entries.insert(key, Entry { pattern: entry.pattern, rank, last_access: entry.last_access });
+        }
+        table
+    }
+}
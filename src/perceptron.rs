@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+
+use crate::prefetcher::PatternType;
+#[cfg(feature = "serde")]
+use crate::snapshot::PerceptronState;
+
+const TABLE_BITS: u32 = 10;
+const TABLE_SIZE: usize = 1 << TABLE_BITS;
+const NUM_FEATURES: usize = 8;
+const WEIGHT_MAX: i32 = 15;
+const WEIGHT_MIN: i32 = -16;
+const ACCEPT_THRESHOLD: i32 = 0;
+const TRAIN_THRESHOLD: i32 = 24;
+const PENDING_CAPACITY: usize = 64;
+
+/// Everything the perceptron looks at when judging one prefetch candidate.
+pub(crate) struct CandidateFeatures<'a> {
+    pub pattern_type: &'a PatternType,
+    pub stride: i64,
+    pub deltas: [i64; 3],
+    pub address: u64,
+    pub candidate: u64,
+    pub depth: usize,
+    pub confidence: f64,
+    pub candidate_confidence: f64,
+}
+
+struct PendingCandidate {
+    address: u64,
+    indices: [usize; NUM_FEATURES],
+}
+
+/// Hashed perceptron that accepts or rejects candidates produced by the
+/// pattern detectors.
+///
+/// Each feature is hashed into its own table of small saturating weights and
+/// the candidate is issued when the weights sum to at least zero. Every
+/// judged candidate (accepted or not) is remembered for a while: a demand
+/// access to it trains the weights towards "useful", and ageing out of the
+/// pending queue unused trains them towards "useless".
+pub(crate) struct PerceptronFilter {
+    weights: Vec<i32>,
+    pending: VecDeque<PendingCandidate>,
+}
+
+fn mix(feature: usize, value: u64) -> usize {
+    // splitmix64 finaliser, salted per feature so tables do not alias
+    let mut x = value ^ ((feature as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^= x >> 31;
+    feature * TABLE_SIZE + (x as usize & (TABLE_SIZE - 1))
+}
+
+fn pattern_id(pattern_type: &PatternType) -> u64 {
+    match pattern_type {
+        PatternType::Sequential => 1,
+        PatternType::Strided => 2,
+        PatternType::Repeated => 3,
+        PatternType::DeltaSequence => 4,
+        PatternType::Markov => 5,
+        PatternType::Spatial => 7,
+        PatternType::BestOffset => 8,
+        PatternType::Custom(name) => name.bytes().fold(6, |acc, b| acc.rotate_left(5) ^ b as u64),
+        PatternType::Unknown => 0,
+    }
+}
+
+impl PerceptronFilter {
+    pub fn new() -> Self {
+        PerceptronFilter {
+            weights: vec![0; NUM_FEATURES * TABLE_SIZE],
+            pending: VecDeque::with_capacity(PENDING_CAPACITY),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn save(&self) -> PerceptronState {
+        PerceptronState {
+            weights: self.weights.clone(),
+            pending: self.pending.iter().map(|p| (p.address, p.indices.to_vec())).collect(),
+        }
+    }
+
+    /// Rebuild a filter from `state`, or `None` if it does not fit the tables.
+    #[cfg(feature = "serde")]
+    pub fn restore(state: PerceptronState) -> Option<Self> {
+        if state.weights.len() != NUM_FEATURES * TABLE_SIZE || state.pending.len() > PENDING_CAPACITY {
+            return None;
+        }
+        let mut pending = VecDeque::with_capacity(PENDING_CAPACITY);
+        for (address, indices) in state.pending {
+            let indices: [usize; NUM_FEATURES] = indices.try_into().ok()?;
+            if indices.iter().any(|&index| index >= state.weights.len()) {
+                return None;
+            }
+            pending.push_back(PendingCandidate { address, indices });
+        }
+        Some(PerceptronFilter { weights: state.weights, pending })
+    }
+
+    fn indices(features: &CandidateFeatures) -> [usize; NUM_FEATURES] {
+        let pattern = pattern_id(features.pattern_type);
+        let deltas = features.deltas.iter().fold(0u64, |acc, &d| acc.rotate_left(21) ^ d as u64);
+        let confidence_bucket = (features.confidence.clamp(0.0, 1.0) * 8.0) as u64;
+        let candidate_bucket = (features.candidate_confidence.clamp(0.0, 1.0) * 8.0) as u64;
+        [
+            mix(0, pattern << 8 | features.depth as u64),
+            mix(1, features.stride as u64),
+            mix(2, deltas),
+            mix(3, features.candidate & 0xfff),
+            mix(4, features.candidate >> 12),
+            mix(5, pattern << 8 | confidence_bucket),
+            mix(6, features.candidate.wrapping_sub(features.address)),
+            mix(7, candidate_bucket << 8 | features.depth as u64),
+        ]
+    }
+
+    fn sum(&self, indices: &[usize; NUM_FEATURES]) -> i32 {
+        indices.iter().map(|&i| self.weights[i]).sum()
+    }
+
+    fn train(&mut self, indices: &[usize; NUM_FEATURES], useful: bool) {
+        let sum = self.sum(indices);
+        let predicted = sum >= ACCEPT_THRESHOLD;
+        if predicted != useful || sum.abs() < TRAIN_THRESHOLD {
+            for &i in indices {
+                let w = &mut self.weights[i];
+                *w = if useful { (*w + 1).min(WEIGHT_MAX) } else { (*w - 1).max(WEIGHT_MIN) };
+            }
+        }
+    }
+
+    /// Decide whether a candidate should be issued and remember it for training.
+    pub fn accept(&mut self, features: &CandidateFeatures) -> bool {
+        let indices = Self::indices(features);
+        let accepted = self.sum(&indices) >= ACCEPT_THRESHOLD;
+
+        if let Some(pos) = self.pending.iter().position(|p| p.address == features.candidate) {
+            self.pending.remove(pos);
+        } else if self.pending.len() >= PENDING_CAPACITY {
+            if let Some(expired) = self.pending.pop_front() {
+                self.train(&expired.indices, false);
+            }
+        }
+        self.pending.push_back(PendingCandidate { address: features.candidate, indices });
+
+        accepted
+    }
+
+    /// Train on a demand access: a pending candidate for it was useful.
+    pub fn observe(&mut self, address: u64) {
+        if let Some(pos) = self.pending.iter().position(|p| p.address == address) {
+            if let Some(hit) = self.pending.remove(pos) {
+                self.train(&hit.indices, true);
+            }
+        }
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+
+use crate::address::Address;
+use crate::config::PrefetcherConfig;
+#[cfg(feature = "serde")]
+use crate::markov::MarkovState;
+use crate::markov::{MarkovMode, MarkovPredictor};
+use crate::prefetcher::PatternType;
+use crate::spatial::SpatialPredictor;
+
+/// What a predictor sees of a single access.
+#[derive(Debug, Clone, Copy)]
+pub struct AccessContext<'a, A: Address> {
+    pub stream_id: u64,
+    pub address: A,
+    /// Recent addresses of the stream, oldest first, ending with `address`.
+    pub history: &'a [A],
+}
+
+/// An address proposed for prefetching together with the predictor's
+/// confidence in it, between 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate<A: Address> {
+    pub address: A,
+    pub confidence: f64,
+}
+
+/// How `PredictivePrefetcher` chooses between the candidates of its predictors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SelectionMode {
+    /// Consult predictors in order and use the first one that proposes
+    /// candidates.
+    #[default]
+    FirstMatch,
+    /// Run every predictor, track each one's recent accuracy per stream and
+    /// use the most accurate predictor that proposes candidates, like a
+    /// tournament branch predictor.
+    Tournament,
+    /// Run every predictor and rank candidates by the sum of their
+    /// confidences weighted by each predictor's recent accuracy.
+    Weighted,
+}
+
+/// A pattern detector that can be plugged into `PredictivePrefetcher`.
+///
+/// For every access the prefetcher first calls `observe` on all predictors,
+/// then asks them for candidates with `predict`. Once a prefetch issued from
+/// a predictor's candidates is used or expires, the predictor receives
+/// `feedback`.
+pub trait Predictor<A: Address>: Send {
+    /// Pattern type reported for the candidates of this predictor.
+    fn pattern_type(&self) -> PatternType;
+
+    /// Learn from an access. Called for every access, before `predict`.
+    fn observe(&mut self, _access: &AccessContext<A>) {}
+
+    /// Propose up to `max_candidates` addresses likely to follow `access`,
+    /// most likely first. An empty result means the pattern is not recognised.
+    fn predict(&mut self, access: &AccessContext<A>, max_candidates: usize) -> Vec<Candidate<A>>;
+
+    /// Outcome of a prefetch of `address` issued from this predictor's
+    /// candidates: `hit` if a demand access used it before it expired.
+    fn feedback(&mut self, _stream_id: u64, _address: A, _hit: bool) {}
+
+    /// Drop any state kept for a stream that is no longer tracked.
+    fn forget_stream(&mut self, _stream_id: u64) {}
+
+    /// Learned state to include in a snapshot, if the predictor keeps any.
+    #[cfg(feature = "serde")]
+    fn save_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Replace the learned state with one returned by `save_state`.
+    #[cfg(feature = "serde")]
+    fn load_state(&mut self, _state: &[u8]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// The built-in predictors, in the order they are consulted.
+pub fn default_predictors<A: Address>() -> Vec<Box<dyn Predictor<A>>> {
+    configured_predictors(&PrefetcherConfig::default())
+}
+
+/// The built-in predictors with the detection ratios of `config`.
+pub(crate) fn configured_predictors<A: Address>(config: &PrefetcherConfig) -> Vec<Box<dyn Predictor<A>>> {
+    vec![
+        Box::new(DeltaSequencePredictor),
+        Box::new(SequentialPredictor::new(config.sequential_ratio)),
+        Box::new(StridedPredictor::new(config.stride_ratio)),
+        Box::new(RepeatedPredictor::new(config.repeat_ratio)),
+        Box::new(SpatialPredictor::new(config.region_size)),
+        Box::new(MarkovChainPredictor::new(1, MarkovMode::default())),
+    ]
+}
+
+/// Matches needed out of `deltas` to reach `ratio`, rounded down.
+fn required_matches(deltas: usize, ratio: f64) -> usize {
+    (deltas as f64 * ratio) as usize
+}
+
+pub(crate) fn walk<A: Address>(
+    address: A,
+    deltas: impl Iterator<Item = i64>,
+    confidence: f64,
+) -> Vec<Candidate<A>> {
+    let mut next = address;
+    deltas
+        .map_while(|delta| {
+            next = next.checked_offset(delta)?;
+            Some(Candidate { address: next, confidence })
+        })
+        .collect()
+}
+
+/// Consecutive addresses: 1, 2, 3, 4...
+#[derive(Debug, Clone, Copy)]
+pub struct SequentialPredictor {
+    min_ratio: f64,
+}
+
+impl SequentialPredictor {
+    /// Detect a sequential pattern once at least `min_ratio` of the deltas
+    /// in the history are 1.
+    pub fn new(min_ratio: f64) -> Self {
+        SequentialPredictor { min_ratio }
+    }
+}
+
+impl Default for SequentialPredictor {
+    fn default() -> Self {
+        SequentialPredictor::new(PrefetcherConfig::default().sequential_ratio)
+    }
+}
+
+impl<A: Address> Predictor<A> for SequentialPredictor {
+    fn pattern_type(&self) -> PatternType {
+        PatternType::Sequential
+    }
+
+    fn predict(&mut self, access: &AccessContext<A>, max_candidates: usize) -> Vec<Candidate<A>> {
+        let history = access.history;
+        if history.len() < 2 {
+            return Vec::new();
+        }
+
+        let matches = history.windows(2).filter(|w| w[1].delta(w[0]) == 1).count();
+        if matches < required_matches(history.len() - 1, self.min_ratio) {
+            return Vec::new();
+        }
+        let confidence = matches as f64 / (history.len() - 1) as f64;
+        walk(access.address, std::iter::repeat_n(1, max_candidates), confidence)
+    }
+}
+
+/// A dominant fixed stride: 2, 4, 6, 8...
+#[derive(Debug, Clone, Copy)]
+pub struct StridedPredictor {
+    min_ratio: f64,
+}
+
+impl StridedPredictor {
+    /// Detect a strided pattern once the most common delta covers at least
+    /// `min_ratio` of the deltas in the history.
+    pub fn new(min_ratio: f64) -> Self {
+        StridedPredictor { min_ratio }
+    }
+}
+
+impl Default for StridedPredictor {
+    fn default() -> Self {
+        StridedPredictor::new(PrefetcherConfig::default().stride_ratio)
+    }
+}
+
+impl<A: Address> Predictor<A> for StridedPredictor {
+    fn pattern_type(&self) -> PatternType {
+        PatternType::Strided
+    }
+
+    fn predict(&mut self, access: &AccessContext<A>, max_candidates: usize) -> Vec<Candidate<A>> {
+        let history = access.history;
+        if history.len() < 2 {
+            return Vec::new();
+        }
+
+        let mut stride_matches = HashMap::new();
+        for w in history.windows(2) {
+            *stride_matches.entry(w[1].delta(w[0])).or_insert(0) += 1;
+        }
+
+        match stride_matches.iter().max_by_key(|&(_, count)| count) {
+            Some((&stride, &count)) if count >= required_matches(history.len() - 1, self.min_ratio) && stride != 0 => {
+                let confidence = count as f64 / (history.len() - 1) as f64;
+                walk(access.address, std::iter::repeat_n(stride, max_candidates), confidence)
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A recurring cycle of addresses: 1, 2, 3, 1, 2, 3...
+#[derive(Debug, Clone, Copy)]
+pub struct RepeatedPredictor {
+    min_ratio: f64,
+}
+
+impl RepeatedPredictor {
+    /// Detect a cycle once more than `min_ratio` of the history repeats it.
+    pub fn new(min_ratio: f64) -> Self {
+        RepeatedPredictor { min_ratio }
+    }
+}
+
+impl Default for RepeatedPredictor {
+    fn default() -> Self {
+        RepeatedPredictor::new(PrefetcherConfig::default().repeat_ratio)
+    }
+}
+
+impl<A: Address> Predictor<A> for RepeatedPredictor {
+    fn pattern_type(&self) -> PatternType {
+        PatternType::Repeated
+    }
+
+    fn predict(&mut self, access: &AccessContext<A>, max_candidates: usize) -> Vec<Candidate<A>> {
+        let history = access.history;
+        if history.len() < 3 {
+            return Vec::new();
+        }
+
+        for len in 2..=history.len() / 2 {
+            let possible = history.len() - len;
+            let matches = (0..possible).filter(|&i| history[i] == history[i + len]).count();
+            let confidence = matches as f64 / possible as f64;
+            if possible > 0 && confidence > self.min_ratio {
+                let cycle_start = history.len() - len;
+                return history[cycle_start..]
+                    .iter()
+                    .take(max_candidates)
+                    .map(|&address| Candidate { address, confidence })
+                    .collect();
+            }
+        }
+
+        Vec::new()
+    }
+}
+
+/// A repeating sequence of deltas: +1, +1, +6, +1, +1, +6...
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeltaSequencePredictor;
+
+impl DeltaSequencePredictor {
+    /// Find the shortest delta sequence (of length two or more) that repeats
+    /// exactly over the whole history.
+    ///
+    /// Sequences with zero net displacement revisit the same addresses and are
+    /// left to the repeated-pattern detector.
+    fn detect<A: Address>(history: &[A]) -> Option<Vec<i64>> {
+        let deltas: Vec<i64> = history.windows(2).map(|w| w[1].delta(w[0])).collect();
+
+        for period in 2..=deltas.len() / 2 {
+            if !(period..deltas.len()).all(|i| deltas[i] == deltas[i - period]) {
+                continue;
+            }
+            let sequence = &deltas[deltas.len() - period..];
+            if sequence.iter().all(|&d| d == sequence[0]) {
+                return None;
+            }
+            let displacement = sequence.iter().fold(0i64, |acc, &d| acc.wrapping_add(d));
+            if displacement == 0 {
+                return None;
+            }
+            return Some(sequence.to_vec());
+        }
+
+        None
+    }
+}
+
+impl<A: Address> Predictor<A> for DeltaSequencePredictor {
+    fn pattern_type(&self) -> PatternType {
+        PatternType::DeltaSequence
+    }
+
+    fn predict(&mut self, access: &AccessContext<A>, max_candidates: usize) -> Vec<Candidate<A>> {
+        match Self::detect(access.history) {
+            // Replay the learned delta sequence from the current address
+            Some(sequence) => walk(
+                access.address,
+                sequence.iter().copied().cycle().take(max_candidates),
+                1.0,
+            ),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Irregular but repeatable sequences, learned by one Markov chain per stream.
+pub struct MarkovChainPredictor<A: Address> {
+    order: usize,
+    mode: MarkovMode,
+    chains: HashMap<u64, MarkovPredictor<A>>,
+}
+
+impl<A: Address> MarkovChainPredictor<A> {
+    pub fn new(order: usize, mode: MarkovMode) -> Self {
+        MarkovChainPredictor {
+            order,
+            mode,
+            chains: HashMap::new(),
+        }
+    }
+}
+
+impl<A: Address> Predictor<A> for MarkovChainPredictor<A> {
+    fn pattern_type(&self) -> PatternType {
+        PatternType::Markov
+    }
+
+    fn observe(&mut self, access: &AccessContext<A>) {
+        let (order, mode) = (self.order, self.mode);
+        self.chains
+            .entry(access.stream_id)
+            .or_insert_with(|| MarkovPredictor::new(order, mode))
+            .observe(access.address);
+    }
+
+    fn predict(&mut self, access: &AccessContext<A>, max_candidates: usize) -> Vec<Candidate<A>> {
+        self.chains.get(&access.stream_id).map_or_else(Vec::new, |chain| {
+            chain
+                .predict(max_candidates)
+                .into_iter()
+                .map(|(address, confidence)| Candidate { address, confidence })
+                .collect()
+        })
+    }
+
+    fn forget_stream(&mut self, stream_id: u64) {
+        self.chains.remove(&stream_id);
+    }
+
+    #[cfg(feature = "serde")]
+    fn save_state(&self) -> Option<Vec<u8>> {
+        let chains: Vec<(u64, MarkovState)> = self.chains.iter().map(|(&stream_id, chain)| (stream_id, chain.save())).collect();
+        bincode::serialize(&chains).ok()
+    }
+
+    #[cfg(feature = "serde")]
+    fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        let saved: Vec<(u64, MarkovState)> = bincode::deserialize(state).map_err(|err| err.to_string())?;
+        let mut chains = HashMap::with_capacity(saved.len());
+        for (stream_id, chain) in saved {
+            let chain = MarkovPredictor::restore(chain, self.order, self.mode)
+                .ok_or_else(|| format!("Markov chain of stream {} has another order or mode", stream_id))?;
+            chains.insert(stream_id, chain);
+        }
+        self.chains = chains;
+        Ok(())
+    }
+}
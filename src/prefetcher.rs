@@ -1,97 +1,186 @@
 use std::collections::{HashMap, VecDeque};
-use tokio::sync::mpsc;
+
+use crate::address::Address;
+use crate::config::{ConfigError, PrefetcherConfig};
+#[cfg(feature = "async")]
+use crate::delivery::{DeliveryPolicy, PredictionReceiver, PredictionSender};
+use crate::markov::MarkovMode;
+use crate::outstanding::{Issuer, OutstandingPrefetches};
+use crate::pattern_table::{EvictionPolicy, PatternTable};
+use crate::perceptron::{CandidateFeatures, PerceptronFilter};
+use crate::predictor::{
+    configured_predictors, AccessContext, Candidate, MarkovChainPredictor, Predictor, SelectionMode,
+};
+#[cfg(feature = "serde")]
+use crate::snapshot::{AddressType, PredictorState, PrefetcherState, SnapshotError, StatsState, StreamSnapshot};
+use crate::stats::PrefetchStats;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PatternType {
     Sequential,
     Strided,
     Repeated,
+    DeltaSequence,
+    Markov,
+    /// A recurring footprint of offsets within a memory region.
+    Spatial,
+    /// The single offset that best covers recent accesses, across streams.
+    BestOffset,
+    /// Reported by user-supplied predictors.
+    Custom(String),
     Unknown
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AccessPattern {
     pattern_type: PatternType,
-    stride: i32,
+    stride: i64,
+    offsets: Vec<i64>,
     frequency: u32,
     confidence: f64,
-    window_size: usize,
 }
 
-#[derive(Debug)]
-pub struct PredictionBatch {
-    pub address: i32,
-    pub predictions: Vec<i32>,
+/// Prefetch distance and degree of one pattern type in one stream, adapted
+/// by the outcomes of its prefetches. Kept per pattern type rather than per
+/// trigger, since streaming patterns rarely revisit a trigger.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Lookahead {
+    distance: usize,
+    degree: usize,
+    confidence: f64,
+}
+
+impl Lookahead {
+    fn new(config: &PrefetcherConfig) -> Self {
+        Lookahead {
+            distance: config.initial_distance,
+            degree: config.min_window_size,
+            confidence: config.initial_confidence,
+        }
+    }
+
+    /// Late prefetches move the distance further ahead, useless ones bring
+    /// it closer. The degree grows on hits while confidence is high and
+    /// shrinks on useless prefetches while it is low.
+    fn update(&mut self, outcome: Outcome, config: &PrefetcherConfig) {
+        let hit = outcome != Outcome::Useless;
+        let target = if hit { 1.0 } else { 0.0 };
+        self.confidence += config.learning_rate * (target - self.confidence);
+        match outcome {
+            Outcome::Late => self.distance = (self.distance + 1).min(config.max_distance),
+            Outcome::Useless => self.distance = self.distance.saturating_sub(1),
+            Outcome::Timely => {}
+        }
+        if hit {
+            if self.degree < config.max_window_size && self.confidence > config.grow_threshold {
+                self.degree += 1;
+            }
+        } else if self.degree > config.min_window_size && self.confidence < config.shrink_threshold {
+            self.degree -= 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PredictionBatch<A: Address = u64> {
+    pub stream_id: u64,
+    pub address: A,
+    pub predictions: Vec<A>,
     pub pattern_type: PatternType,
     pub confidence: f64,
 }
 
-pub struct PredictivePrefetcher {
-    history: VecDeque<i32>,
-    pattern_table: HashMap<i32, AccessPattern>,
-    history_size: usize,
+/// Stream used by `access` when the caller does not supply a context.
+pub const DEFAULT_STREAM: u64 = 0;
+
+// Accuracy a predictor starts with before it has been scored, and how
+// quickly the running accuracy follows new outcomes
+const INITIAL_ACCURACY: f64 = 0.5;
+const ACCURACY_RATE: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Timely,
+    Late,
+    Useless,
+}
+
+struct StreamState<A: Address> {
+    history: VecDeque<A>,
+    last_access: u64,
+    // Candidates of every predictor for the last access, and each
+    // predictor's running accuracy (ensemble selection only)
+    proposals: Vec<Vec<A>>,
+    accuracy: Vec<f64>,
+}
+
+pub struct PredictivePrefetcher<A: Address = u64> {
+    streams: HashMap<u64, StreamState<A>>,
+    pattern_table: PatternTable<A>,
+    filter: PerceptronFilter,
+    predictors: Vec<Box<dyn Predictor<A>>>,
+    selection: SelectionMode,
+    config: PrefetcherConfig,
+    // Prefetch distance and degree of each pattern type seen in each stream
+    lookahead: HashMap<(u64, PatternType), Lookahead>,
+    max_streams: usize,
+    stream_evictions: u64,
+    clock: u64,
     hits: u32,
     misses: u32,
-    prediction_tx: Option<mpsc::Sender<PredictionBatch>>,
-    min_confidence: f64,
-    max_window_size: usize,
+    stats: PrefetchStats,
+    outstanding: OutstandingPrefetches<A>,
+    min_lead: u64,
+    #[cfg(feature = "async")]
+    prediction_tx: PredictionSender<A>,
+    dropped_batches: u64,
 }
 
 impl AccessPattern {
-    fn new(pattern_type: PatternType, stride: i32, min_confidence: f64) -> Self {
+    fn new<A: Address>(
+        pattern_type: PatternType,
+        address: A,
+        candidates: &[Candidate<A>],
+        config: &PrefetcherConfig,
+    ) -> Self {
+        // Candidates are stored relative to the address so they can be replayed
+        let offsets: Vec<i64> = candidates.iter().map(|c| c.address.delta(address)).collect();
         AccessPattern {
             pattern_type,
-            stride,
+            stride: offsets.first().copied().unwrap_or(0),
+            offsets,
             frequency: 1,
-            confidence: min_confidence,
-            window_size: 2,
+            confidence: config.initial_confidence,
         }
     }
 
-    fn update(&mut self, was_hit: bool, max_window_size: usize) {
+    pub(crate) fn frequency(&self) -> u32 {
+        self.frequency
+    }
+
+    pub(crate) fn confidence(&self) -> f64 {
+        self.confidence
+    }
+
+    fn touch(&mut self) {
         self.frequency += 1;
-        if was_hit {
-            self.confidence = (self.confidence * 0.8) + 0.2;
-            if self.window_size < max_window_size && self.confidence > 0.5 {
-                self.window_size += 1;
-            }
-        } else {
-            self.confidence *= 0.8;
-            if self.window_size > 2 {
-                self.window_size -= 1;
-            }
-        }
     }
 
-    fn generate_predictions(&self, address: i32, history: &VecDeque<i32>) -> Vec<i32> {
+    fn update(&mut self, was_hit: bool, config: &PrefetcherConfig) {
+        let target = if was_hit { 1.0 } else { 0.0 };
+        self.confidence += config.learning_rate * (target - self.confidence);
+    }
+
+    /// Replay `degree` offsets, starting `skip` steps ahead.
+    fn generate_predictions<A: Address>(&self, address: A, min_confidence: f64, skip: usize, degree: usize) -> Vec<A> {
         let mut predictions = Vec::new();
         
-        if self.confidence >= 0.2 {
-            match self.pattern_type {
-                PatternType::Sequential => {
-                    for i in 1..=self.window_size {
-                        predictions.push(address + i as i32);
-                    }
-                },
-                PatternType::Strided => {
-                    let mut next = address;
-                    for _ in 0..self.window_size {
-                        next += self.stride;
-                        predictions.push(next);
-                    }
-                },
-                PatternType::Repeated => {
-                    if let Some(cycle_start) = history.len().checked_sub(self.stride as usize) {
-                        for i in 0..self.window_size {
-                            if let Some(&next) = history.get(cycle_start + i) {
-                                predictions.push(next);
-                            }
-                        }
-                    }
-                },
-                PatternType::Unknown => {
-                    predictions.push(address + 1);
-                }
+        if self.confidence >= min_confidence {
+            for &offset in self.offsets.iter().skip(skip).take(degree) {
+                predictions.extend(address.checked_offset(offset));
             }
         }
         
@@ -99,132 +188,571 @@ impl AccessPattern {
     }
 }
 
-impl PredictivePrefetcher {
+impl<A: Address> PredictivePrefetcher<A> {
+    /// A prefetcher with the default configuration and the given history
+    /// size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `history_size` is zero.
     pub fn new(history_size: usize) -> Self {
-        Self::with_config(history_size, 0.2, 4)
+        let config = PrefetcherConfig::default().with_history_size(history_size);
+        Self::from_config(config).unwrap_or_else(|err| panic!("invalid prefetcher configuration: {}", err))
     }
 
+    /// Shorthand for `from_config` with the most common parameters. New
+    /// patterns start at `min_confidence` if it is above the default
+    /// initial confidence.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the values are invalid; use `from_config` to handle the
+    /// error instead.
     pub fn with_config(history_size: usize, min_confidence: f64, max_window_size: usize) -> Self {
-        PredictivePrefetcher {
-            history: VecDeque::with_capacity(history_size),
-            pattern_table: HashMap::new(),
-            history_size,
+        let default = PrefetcherConfig::default();
+        let config = default.clone()
+            .with_history_size(history_size)
+            .with_min_confidence(min_confidence)
+            .with_initial_confidence(default.initial_confidence.max(min_confidence))
+            .with_window_size(default.min_window_size.min(max_window_size), max_window_size);
+        Self::from_config(config).unwrap_or_else(|err| panic!("invalid prefetcher configuration: {}", err))
+    }
+
+    /// A prefetcher with the given configuration, or the first invalid
+    /// value found in it.
+    pub fn from_config(config: PrefetcherConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+        Ok(PredictivePrefetcher {
+            streams: HashMap::new(),
+            pattern_table: PatternTable::new(4096, EvictionPolicy::default()),
+            filter: PerceptronFilter::new(),
+            predictors: configured_predictors(&config),
+            selection: SelectionMode::default(),
+            config,
+            lookahead: HashMap::new(),
+            max_streams: 64,
+            stream_evictions: 0,
+            clock: 0,
             hits: 0,
             misses: 0,
-            prediction_tx: None,
-            min_confidence,
-            max_window_size,
-        }
+            stats: PrefetchStats::default(),
+            outstanding: OutstandingPrefetches::new(256, 64),
+            min_lead: 2,
+            #[cfg(feature = "async")]
+            prediction_tx: PredictionSender::new(DeliveryPolicy::default(), 100),
+            dropped_batches: 0,
+        })
+    }
+
+    pub fn config(&self) -> &PrefetcherConfig {
+        &self.config
     }
 
-    pub async fn start_async_predictor(&mut self) -> mpsc::Receiver<PredictionBatch> {
-        let (tx, rx) = mpsc::channel(100);
-        self.prediction_tx = Some(tx);
-        rx
+    /// Limit the number of streams tracked by `access_with_context`.
+    ///
+    /// When a new stream arrives and the table is full, the least recently
+    /// accessed stream is forgotten together with its learned patterns.
+    pub fn with_max_streams(mut self, max_streams: usize) -> Self {
+        self.max_streams = max_streams.max(1);
+        self
     }
 
-    fn detect_pattern(&self) -> (PatternType, i32) {
-        if self.history.len() < 2 {
-            return (PatternType::Unknown, 0);
+    /// Bound the pattern table to `capacity` entries, replacing entries
+    /// according to `policy` once it is full. Defaults to 4096 entries with
+    /// LRU replacement.
+    pub fn with_table_capacity(mut self, capacity: usize, policy: EvictionPolicy) -> Self {
+        self.pattern_table = PatternTable::new(capacity, policy);
+        self
+    }
+
+    /// Replace the pattern detectors. By default predictors are consulted in
+    /// order and the first one proposing candidates wins (see
+    /// `with_selection`); `default_predictors` returns the built-in set.
+    pub fn with_predictors(mut self, predictors: Vec<Box<dyn Predictor<A>>>) -> Self {
+        self.predictors = predictors;
+        self
+    }
+
+    /// Configure the Markov predictor used for irregular but repeatable
+    /// accesses. Defaults to a first-order chain over addresses.
+    pub fn with_markov(mut self, order: usize, mode: MarkovMode) -> Self {
+        for predictor in self.predictors.iter_mut() {
+            if predictor.pattern_type() == PatternType::Markov {
+                *predictor = Box::new(MarkovChainPredictor::new(order, mode));
+            }
         }
+        self
+    }
 
-        let vec: Vec<_> = self.history.iter().copied().collect();
-        
-        // Sequential pattern detection
-        let mut sequential_matches = 0;
-        for i in 1..vec.len() {
-            if vec[i] == vec[i - 1] + 1 {
-                sequential_matches += 1;
+    /// Choose how candidates of the predictors are combined.
+    pub fn with_selection(mut self, selection: SelectionMode) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Minimum number of accesses between issuing a prefetch and its use for
+    /// the prefetch to count as timely. Defaults to 2, so a prefetch consumed
+    /// by the very next access is counted as late.
+    pub fn with_min_lead(mut self, min_lead: u64) -> Self {
+        self.min_lead = min_lead;
+        self
+    }
+
+    /// Size the buffer of outstanding prefetches. A prefetch counts as a hit
+    /// when a demand access consumes it while outstanding; it is dropped as
+    /// useless after `lifetime` accesses, or when `capacity` newer prefetches
+    /// push it out. Defaults to 256 prefetches living 64 accesses.
+    pub fn with_outstanding(mut self, capacity: usize, lifetime: u64) -> Self {
+        self.outstanding = OutstandingPrefetches::new(capacity, lifetime);
+        self
+    }
+
+    /// Configure the channel of each subscriber: what to do when `capacity`
+    /// batches are waiting for it. Defaults to blocking on a channel of 100
+    /// batches. Existing subscribers are disconnected.
+    #[cfg(feature = "async")]
+    pub fn with_delivery(mut self, policy: DeliveryPolicy, capacity: usize) -> Self {
+        self.prediction_tx = PredictionSender::new(policy, capacity);
+        self
+    }
+
+    /// Receive the prediction batch of every subsequent access. Any number of
+    /// subscribers can be active; each gets its own channel.
+    #[cfg(feature = "async")]
+    pub fn subscribe(&mut self) -> PredictionReceiver<A> {
+        self.prediction_tx.subscribe()
+    }
+
+    /// Number of subscribers whose receiver is still alive.
+    #[cfg(feature = "async")]
+    pub fn subscriber_count(&self) -> usize {
+        self.prediction_tx.subscriber_count()
+    }
+
+    /// Same as `subscribe`; earlier receivers stay connected.
+    #[cfg(feature = "async")]
+    pub async fn start_async_predictor(&mut self) -> PredictionReceiver<A> {
+        self.subscribe()
+    }
+
+    fn detect_pattern(&mut self, access: &AccessContext<A>) -> (PatternType, Option<usize>, Vec<Candidate<A>>) {
+        if access.history.len() >= 2 {
+            let max_candidates = self.max_candidates();
+            for (index, predictor) in self.predictors.iter_mut().enumerate() {
+                let candidates = predictor.predict(access, max_candidates);
+                if !candidates.is_empty() {
+                    return (predictor.pattern_type(), Some(index), candidates);
+                }
             }
         }
-        if sequential_matches >= (vec.len() - 1) / 2 {
-            return (PatternType::Sequential, 1);
+
+        Self::fallback(access.address)
+    }
+
+    // Enough candidates for the largest degree at the furthest distance
+    fn max_candidates(&self) -> usize {
+        self.config.max_window_size + self.config.max_distance
+    }
+
+    /// Whether candidates of `pattern_type` are successive accesses, so that
+    /// skipping ahead by the prefetch distance makes sense. Markov and merged
+    /// candidates are ranked alternatives for the next access instead, and
+    /// spatial candidates a footprint without an order of use.
+    fn uses_distance(&self, pattern_type: &PatternType) -> bool {
+        self.selection != SelectionMode::Weighted
+            && matches!(
+                pattern_type,
+                PatternType::Sequential
+                    | PatternType::Strided
+                    | PatternType::Repeated
+                    | PatternType::DeltaSequence
+                    | PatternType::BestOffset
+            )
+    }
+
+    fn fallback(address: A) -> (PatternType, Option<usize>, Vec<Candidate<A>>) {
+        let fallback = address.checked_offset(1)
+            .map(|address| Candidate { address, confidence: 0.0 });
+        (PatternType::Unknown, None, fallback.into_iter().collect())
+    }
+
+    /// Score every predictor's candidates for the previous access of the
+    /// stream against the access that actually followed.
+    fn score_predictors(stream: &mut StreamState<A>, address: A) {
+        for (index, proposal) in stream.proposals.iter().enumerate() {
+            if proposal.is_empty() {
+                continue;
+            }
+            let hit = proposal.contains(&address);
+            if let Some(accuracy) = stream.accuracy.get_mut(index) {
+                *accuracy += ACCURACY_RATE * (if hit { 1.0 } else { 0.0 } - *accuracy);
+            }
         }
+    }
 
-        // Strided pattern detection
-        let mut stride_matches = HashMap::new();
-        for i in 1..vec.len() {
-            let stride = vec[i] - vec[i - 1];
-            *stride_matches.entry(stride).or_insert(0) += 1;
+    /// Feed the outcome of an issued prefetch back to the pattern, the
+    /// distance and degree of its pattern type, and the predictor that
+    /// produced it.
+    fn record_outcome(&mut self, issuer: Issuer<A>, address: A, outcome: Outcome) {
+        let hit = outcome != Outcome::Useless;
+        let config = &self.config;
+        self.pattern_table.update((issuer.stream_id, issuer.trigger), |pattern| {
+            pattern.update(hit, config);
+        });
+        if let Some(lookahead) = self.lookahead.get_mut(&(issuer.stream_id, issuer.pattern_type)) {
+            lookahead.update(outcome, config);
+        }
+        if let Some(predictor) = issuer.source.and_then(|i| self.predictors.get_mut(i)) {
+            predictor.feedback(issuer.stream_id, address, hit);
         }
+    }
 
-        if let Some((&stride, &count)) = stride_matches.iter().max_by_key(|&(_, count)| count) {
-            if count >= (vec.len() - 1) / 2 && stride != 0 {
-                return (PatternType::Strided, stride);
-            }
+    fn select_ensemble(
+        &mut self,
+        access: &AccessContext<A>,
+        stream: &mut StreamState<A>,
+    ) -> (PatternType, Option<usize>, Vec<Candidate<A>>) {
+        stream.accuracy.resize(self.predictors.len(), INITIAL_ACCURACY);
+        let proposals: Vec<Vec<Candidate<A>>> = if access.history.len() >= 2 {
+            let max_candidates = self.max_candidates();
+            self.predictors.iter_mut()
+                .map(|predictor| predictor.predict(access, max_candidates))
+                .collect()
+        } else {
+            vec![Vec::new(); self.predictors.len()]
+        };
+        stream.proposals = proposals.iter()
+            .map(|candidates| candidates.iter().map(|c| c.address).collect())
+            .collect();
+
+        let selected = match self.selection {
+            SelectionMode::Weighted => Self::merge_weighted(&proposals, &stream.accuracy, self.config.max_window_size),
+            _ => proposals.iter()
+                .enumerate()
+                .filter(|(_, candidates)| !candidates.is_empty())
+                // Earlier predictors win ties
+                .max_by(|(a, _), (b, _)| stream.accuracy[*a].total_cmp(&stream.accuracy[*b]).then(b.cmp(a)))
+                .map(|(index, candidates)| (index, candidates.clone())),
+        };
+
+        match selected {
+            Some((index, candidates)) => (self.predictors[index].pattern_type(), Some(index), candidates),
+            None => Self::fallback(access.address),
         }
+    }
 
-        // Repeated pattern detection
-        if vec.len() >= 3 {
-            for len in 2..=vec.len()/2 {
-                let mut matches = 0;
-                let mut possible = 0;
-                for i in 0..vec.len() - len {
-                    if vec[i] == vec[i + len] {
-                        matches += 1;
+    /// Rank all proposed addresses by accuracy-weighted confidence. The
+    /// predictor contributing most to the top address is reported as source.
+    fn merge_weighted(
+        proposals: &[Vec<Candidate<A>>],
+        accuracy: &[f64],
+        max_candidates: usize,
+    ) -> Option<(usize, Vec<Candidate<A>>)> {
+        // (address, total weight, best source, best contribution)
+        let mut ranked: Vec<(A, f64, usize, f64)> = Vec::new();
+        let mut total_accuracy = 0.0;
+        for (index, candidates) in proposals.iter().enumerate() {
+            if candidates.is_empty() {
+                continue;
+            }
+            total_accuracy += accuracy[index];
+            for candidate in candidates {
+                let weight = accuracy[index] * candidate.confidence;
+                match ranked.iter_mut().find(|entry| entry.0 == candidate.address) {
+                    Some(entry) => {
+                        entry.1 += weight;
+                        if weight > entry.3 {
+                            entry.2 = index;
+                            entry.3 = weight;
+                        }
                     }
-                    possible += 1;
+                    None => ranked.push((candidate.address, weight, index, weight)),
                 }
-                if possible > 0 && matches as f64 / possible as f64 > 0.5 {
-                    return (PatternType::Repeated, len as i32);
+            }
+        }
+
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let source = ranked.first()?.2;
+        let candidates = ranked.into_iter()
+            .take(max_candidates)
+            .map(|(address, weight, _, _)| Candidate {
+                address,
+                confidence: if total_accuracy > 0.0 { (weight / total_accuracy).min(1.0) } else { 0.0 },
+            })
+            .collect();
+        Some((source, candidates))
+    }
+
+    fn recent_deltas(history: &[A]) -> [i64; 3] {
+        let mut deltas = [0; 3];
+        let mut iter = history.iter().rev();
+        if let Some(mut next) = iter.next().copied() {
+            for (delta, &prev) in deltas.iter_mut().zip(iter) {
+                *delta = next.delta(prev);
+                next = prev;
+            }
+        }
+        deltas
+    }
+
+    fn filter_predictions(
+        &mut self,
+        access: &AccessContext<A>,
+        pattern: &AccessPattern,
+        candidates: &[Candidate<A>],
+        predictions: Vec<A>,
+        skip: usize,
+    ) -> Vec<A> {
+        let deltas = Self::recent_deltas(access.history);
+        predictions
+            .into_iter()
+            .enumerate()
+            .map(|(index, candidate)| (skip + index, candidate))
+            .filter(|&(depth, candidate)| {
+                self.filter.accept(&CandidateFeatures {
+                    pattern_type: &pattern.pattern_type,
+                    stride: pattern.stride,
+                    deltas,
+                    address: access.address.to_bits(),
+                    candidate: candidate.to_bits(),
+                    depth,
+                    confidence: pattern.confidence,
+                    candidate_confidence: candidates.get(depth).map_or(0.0, |c| c.confidence),
+                })
+            })
+            .map(|(_, candidate)| candidate)
+            .collect()
+    }
+
+    fn take_stream(&mut self, stream_id: u64) -> StreamState<A> {
+        self.clock += 1;
+        if let Some(mut stream) = self.streams.remove(&stream_id) {
+            stream.last_access = self.clock;
+            return stream;
+        }
+
+        // Make room for the new stream by forgetting the least recently used one
+        if self.streams.len() >= self.max_streams {
+            let victim = self.streams.iter()
+                .min_by_key(|(_, stream)| stream.last_access)
+                .map(|(&id, _)| id);
+            if let Some(victim) = victim {
+                self.streams.remove(&victim);
+                self.pattern_table.remove_stream(victim);
+                self.lookahead.retain(|&(stream_id, _), _| stream_id != victim);
+                for predictor in self.predictors.iter_mut() {
+                    predictor.forget_stream(victim);
                 }
+                self.stream_evictions += 1;
             }
         }
 
-        (PatternType::Unknown, 0)
+        StreamState {
+            history: VecDeque::with_capacity(self.config.history_size),
+            last_access: self.clock,
+            proposals: Vec::new(),
+            accuracy: Vec::new(),
+        }
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn access(&mut self, address: A) -> Vec<A> {
+        self.access_with_context(DEFAULT_STREAM, address).await
     }
 
-    pub async fn access(&mut self, address: i32) -> Vec<i32> {
-        // Check if current access was predicted
-        let was_hit = if let Some(prev_addr) = self.history.back() {
-            if let Some(pattern) = self.pattern_table.get(prev_addr) {
-                let prev_predictions = pattern.generate_predictions(*prev_addr, &self.history);
-                prev_predictions.contains(&address)
-            } else {
-                false
+    /// Record an access belonging to `stream_id` (a program counter, stream
+    /// or thread identifier) and return the predicted next addresses.
+    ///
+    /// Each stream keeps its own history and patterns, so interleaved streams
+    /// do not disturb each other's pattern detection.
+    #[cfg(feature = "async")]
+    pub async fn access_with_context(&mut self, stream_id: u64, address: A) -> Vec<A> {
+        let batch = self.process(stream_id, address);
+        if self.prediction_tx.has_subscribers() {
+            self.dropped_batches += self.prediction_tx.send(batch.clone()).await;
+        }
+        batch.predictions
+    }
+
+    /// Synchronous `access`: record an access to the default stream and
+    /// return the predicted next addresses.
+    pub fn access_sync(&mut self, address: A) -> Vec<A> {
+        self.observe(DEFAULT_STREAM, address)
+    }
+
+    /// Synchronous `access_with_context`. With an async predictor started,
+    /// the batch is sent without waiting, applying the delivery policy.
+    pub fn observe(&mut self, stream_id: u64, address: A) -> Vec<A> {
+        let batch = self.process(stream_id, address);
+        #[cfg(feature = "async")]
+        if self.prediction_tx.has_subscribers() {
+            self.dropped_batches += self.prediction_tx.try_send(batch.clone());
+        }
+        batch.predictions
+    }
+
+    /// `access_sync` for each address in turn, returning the predictions
+    /// made after each one. Accesses of one stream depend on each other, so
+    /// they are processed in order; `SharedPrefetcher::access_batch` spreads
+    /// the streams of a batch over threads.
+    pub fn access_batch(&mut self, addresses: &[A]) -> Vec<Vec<A>> {
+        addresses.iter().map(|&address| self.access_sync(address)).collect()
+    }
+
+    pub(crate) fn process(&mut self, stream_id: u64, address: A) -> PredictionBatch<A> {
+        // Train the perceptron on candidates this access consumed
+        self.filter.observe(address.to_bits());
+
+        let mut stream = self.take_stream(stream_id);
+        let now = self.clock;
+        self.stats.record_demand();
+        for (expired, issuer) in self.outstanding.expire(now, &mut self.stats) {
+            self.record_outcome(issuer, expired, Outcome::Useless);
+        }
+
+        // The access is a hit if any outstanding prefetch predicted it
+        match self.outstanding.consume(address, now, self.min_lead, &mut self.stats) {
+            Some((issuer, late)) => {
+                self.hits += 1;
+                self.record_outcome(issuer, address, if late { Outcome::Late } else { Outcome::Timely });
             }
-        } else {
-            false
-        };
+            None => self.misses += 1,
+        }
 
-        // Update hits/misses
-        if was_hit {
-            self.hits += 1;
-        } else if !self.pattern_table.is_empty() {
-            self.misses += 1;
+        let ensemble = self.selection != SelectionMode::FirstMatch;
+        if ensemble {
+            Self::score_predictors(&mut stream, address);
         }
 
         // Update history
-        self.history.push_back(address);
-        if self.history.len() > self.history_size {
-            self.history.pop_front();
+        stream.history.push_back(address);
+        if stream.history.len() > self.config.history_size {
+            stream.history.pop_front();
         }
 
         // Detect pattern and create new pattern
-        let (pattern_type, stride) = self.detect_pattern();
-        let new_pattern = AccessPattern::new(pattern_type.clone(), stride, self.min_confidence);
-        let predictions = new_pattern.generate_predictions(address, &self.history);
-
-        // Update pattern table
-        if let Some(pattern) = self.pattern_table.get_mut(&address) {
-            pattern.update(was_hit, self.max_window_size);
+        let history: Vec<A> = stream.history.iter().copied().collect();
+        let access = AccessContext { stream_id, address, history: &history };
+        for predictor in self.predictors.iter_mut() {
+            predictor.observe(&access);
+        }
+        let (pattern_type, source, candidates) = if ensemble {
+            self.select_ensemble(&access, &mut stream)
+        } else {
+            self.detect_pattern(&access)
+        };
+        self.streams.insert(stream_id, stream);
+        let mut new_pattern = AccessPattern::new(pattern_type.clone(), address, &candidates, &self.config);
+        // A known trigger keeps the confidence learned from feedback, which
+        // is checked as well as the predictor's own
+        if let Some(known) = self.pattern_table.get(&(stream_id, address)) {
+            new_pattern.confidence = known.confidence;
+        }
+        let confident = candidates.iter().any(|c| c.confidence >= self.config.min_confidence);
+        let lookahead = *self.lookahead
+            .entry((stream_id, pattern_type.clone()))
+            .or_insert_with(|| Lookahead::new(&self.config));
+        // Skip ahead by the distance, but always leave a candidate to issue
+        let skip = if self.uses_distance(&pattern_type) {
+            lookahead.distance.min(candidates.len().saturating_sub(1))
         } else {
-            self.pattern_table.insert(address, new_pattern);
+            0
+        };
+        let predictions = if confident {
+            new_pattern.generate_predictions(address, self.config.min_confidence, skip, lookahead.degree)
+        } else {
+            Vec::new()
+        };
+        let predictions = self.filter_predictions(&access, &new_pattern, &candidates, predictions, skip);
+        let issuer = Issuer { stream_id, trigger: address, pattern_type: pattern_type.clone(), source };
+        for &prediction in &predictions {
+            let displaced = self.outstanding.issue(prediction, &issuer, now, &mut self.stats);
+            if let Some((displaced, displaced_issuer)) = displaced {
+                self.record_outcome(displaced_issuer, displaced, Outcome::Useless);
+            }
         }
 
-        // Send async predictions if configured
-        if let Some(tx) = &self.prediction_tx {
-            let confidence = self.pattern_table.get(&address).map_or(0.0, |p| p.confidence);
-            let batch = PredictionBatch {
-                address,
-                predictions: predictions.clone(),
-                pattern_type,
-                confidence,
-            };
-            let _ = tx.send(batch).await;
+        // Update pattern table
+        self.pattern_table.update_or_insert((stream_id, address), new_pattern, |pattern| pattern.touch());
+
+        let confidence = self.pattern_table.get(&(stream_id, address)).map_or(0.0, |p| p.confidence);
+        PredictionBatch {
+            stream_id,
+            address,
+            predictions,
+            pattern_type,
+            confidence,
         }
+    }
 
-        predictions
+    /// Number of patterns currently held in the pattern table.
+    pub fn pattern_count(&self) -> usize {
+        self.pattern_table.len()
+    }
+
+    /// Number of patterns evicted from the pattern table so far.
+    pub fn pattern_evictions(&self) -> u64 {
+        self.pattern_table.evictions()
+    }
+
+    /// Number of streams evicted from the stream table so far.
+    pub fn stream_evictions(&self) -> u64 {
+        self.stream_evictions
+    }
+
+    /// Running accuracy of each predictor for `stream_id`, in predictor
+    /// order. Only maintained in ensemble selection modes.
+    pub fn predictor_accuracy(&self, stream_id: u64) -> Vec<(PatternType, f64)> {
+        let Some(stream) = self.streams.get(&stream_id) else {
+            return Vec::new();
+        };
+        self.predictors.iter()
+            .zip(stream.accuracy.iter())
+            .map(|(predictor, &accuracy)| (predictor.pattern_type(), accuracy))
+            .collect()
+    }
+
+    /// Prefetch distance currently used for `pattern_type` in `stream_id`,
+    /// or `None` if the stream has not shown that pattern.
+    pub fn prefetch_distance(&self, stream_id: u64, pattern_type: &PatternType) -> Option<usize> {
+        self.lookahead.get(&(stream_id, pattern_type.clone())).map(|lookahead| lookahead.distance)
+    }
+
+    /// Prefetch degree currently used for `pattern_type` in `stream_id`: the
+    /// number of predictions issued per access. `None` if the stream has not
+    /// shown that pattern.
+    pub fn prefetch_degree(&self, stream_id: u64, pattern_type: &PatternType) -> Option<usize> {
+        self.lookahead.get(&(stream_id, pattern_type.clone())).map(|lookahead| lookahead.degree)
+    }
+
+    /// Number of issued prefetches not yet consumed or expired.
+    pub fn outstanding_count(&self) -> usize {
+        self.outstanding.len()
+    }
+
+    /// Number of streams currently tracked.
+    pub fn stream_count(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Snapshot of the prefetch statistics.
+    pub fn stats(&self) -> PrefetchStats {
+        self.stats.clone()
+    }
+
+    /// Number of prediction batches dropped or coalesced because a subscriber
+    /// fell behind, counted once per subscriber.
+    pub fn dropped_batches(&self) -> u64 {
+        self.dropped_batches
+    }
+
+    /// Reset all statistics, including the counters of `get_stats` and
+    /// `dropped_batches`. Outstanding prefetches stay tracked.
+    pub fn reset_stats(&mut self) {
+        self.stats.reset();
+        self.hits = 0;
+        self.misses = 0;
+        self.dropped_batches = 0;
     }
 
     pub fn get_stats(&self) -> (u32, u32, f64) {
@@ -235,4 +763,129 @@ impl PredictivePrefetcher {
         };
         (self.hits, self.misses, accuracy)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "serde")]
+impl<A: Address> PredictivePrefetcher<A> {
+    pub(crate) fn snapshot_state(&self, address_type: AddressType) -> PrefetcherState {
+        PrefetcherState {
+            address_type,
+            config: self.config.clone(),
+            selection: self.selection,
+            max_streams: self.max_streams,
+            min_lead: self.min_lead,
+            clock: self.clock,
+            hits: self.hits,
+            misses: self.misses,
+            stream_evictions: self.stream_evictions,
+            dropped_batches: self.dropped_batches,
+            stats: StatsState::from(&self.stats),
+            streams: self.streams
+                .iter()
+                .map(|(&stream_id, stream)| StreamSnapshot {
+                    stream_id,
+                    history: stream.history.iter().map(|a| a.to_bits()).collect(),
+                    last_access: stream.last_access,
+                    proposals: stream.proposals
+                        .iter()
+                        .map(|proposal| proposal.iter().map(|a| a.to_bits()).collect())
+                        .collect(),
+                    accuracy: stream.accuracy.clone(),
+                })
+                .collect(),
+            patterns: self.pattern_table.save(),
+            lookahead: self.lookahead
+                .iter()
+                .map(|((stream_id, pattern_type), &lookahead)| (*stream_id, pattern_type.clone(), lookahead))
+                .collect(),
+            perceptron: self.filter.save(),
+            outstanding: self.outstanding.save(),
+            predictors: self.predictors
+                .iter()
+                .map(|predictor| PredictorState {
+                    pattern_type: predictor.pattern_type(),
+                    state: predictor.save_state(),
+                })
+                .collect(),
+        }
+    }
+
+    pub(crate) fn restore_state(&mut self, state: PrefetcherState) -> Result<(), SnapshotError> {
+        state.config.validate()
+            .map_err(|err| SnapshotError::Incompatible(format!("invalid configuration: {}", err)))?;
+        // The predictors were built from the current configuration and keep
+        // using its detection settings
+        let detection = |config: &PrefetcherConfig| {
+            (config.sequential_ratio, config.stride_ratio, config.repeat_ratio, config.region_size)
+        };
+        if detection(&state.config) != detection(&self.config) {
+            return Err(SnapshotError::Incompatible(format!(
+                "snapshot was taken with detection ratios and region size {:?}, prefetcher uses {:?}",
+                detection(&state.config),
+                detection(&self.config)
+            )));
+        }
+        let saved: Vec<_> = state.predictors.iter().map(|p| &p.pattern_type).collect();
+        let current: Vec<_> = self.predictors.iter().map(|p| p.pattern_type()).collect();
+        if saved.len() != current.len() || saved.iter().zip(&current).any(|(s, c)| *s != c) {
+            return Err(SnapshotError::Incompatible(format!(
+                "snapshot was taken with predictors {:?}, prefetcher uses {:?}",
+                saved, current
+            )));
+        }
+        let filter = PerceptronFilter::restore(state.perceptron)
+            .ok_or_else(|| SnapshotError::Incompatible("perceptron state does not fit the filter".to_string()))?;
+
+        // Load the predictors first, rolling back if one rejects its state,
+        // so a failed load leaves the prefetcher as it was
+        let backup: Vec<_> = self.predictors.iter().map(|p| p.save_state()).collect();
+        for (index, saved) in state.predictors.iter().enumerate() {
+            let Some(bytes) = &saved.state else { continue };
+            if let Err(message) = self.predictors[index].load_state(bytes) {
+                for (predictor, bytes) in self.predictors.iter_mut().zip(&backup).take(index) {
+                    if let Some(bytes) = bytes {
+                        let _ = predictor.load_state(bytes);
+                    }
+                }
+                return Err(SnapshotError::Incompatible(format!(
+                    "{:?} predictor state: {}",
+                    saved.pattern_type, message
+                )));
+            }
+        }
+
+        self.config = state.config;
+        self.selection = state.selection;
+        self.max_streams = state.max_streams.max(1);
+        self.min_lead = state.min_lead;
+        self.clock = state.clock;
+        self.hits = state.hits;
+        self.misses = state.misses;
+        self.stream_evictions = state.stream_evictions;
+        self.dropped_batches = state.dropped_batches;
+        self.stats = state.stats.into();
+        self.streams = state.streams
+            .into_iter()
+            .map(|stream| {
+                let restored = StreamState {
+                    history: stream.history.into_iter().map(A::from_bits).collect(),
+                    last_access: stream.last_access,
+                    proposals: stream.proposals
+                        .into_iter()
+                        .map(|proposal| proposal.into_iter().map(A::from_bits).collect())
+                        .collect(),
+                    accuracy: stream.accuracy,
+                };
+                (stream.stream_id, restored)
+            })
+            .collect();
+        self.pattern_table = PatternTable::restore(state.patterns);
+        self.lookahead = state.lookahead
+            .into_iter()
+            .map(|(stream_id, pattern_type, lookahead)| ((stream_id, pattern_type), lookahead))
+            .collect();
+        self.filter = filter;
+        self.outstanding.restore(state.outstanding);
+        Ok(())
+    }
+}
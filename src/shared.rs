@@ -0,0 +1,155 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use rayon::prelude::*;
+
+use crate::address::Address;
+use crate::config::{ConfigError, PrefetcherConfig};
+use crate::prefetcher::PredictivePrefetcher;
+use crate::stats::PrefetchStats;
+
+/// A prefetcher that many threads or tasks can report accesses to at once.
+///
+/// Streams are spread over independent shards, each a `PredictivePrefetcher`
+/// behind its own lock, so accesses to streams in different shards never
+/// wait for each other. A stream always maps to the same shard and keeps
+/// everything it learns there; an outstanding prefetch is only matched by
+/// accesses to streams of the same shard, and the shard's clock (prefetch
+/// lifetime, lead, stream recency) counts only those accesses.
+pub struct SharedPrefetcher<A: Address = u64> {
+    shards: Vec<Mutex<PredictivePrefetcher<A>>>,
+}
+
+impl<A: Address> SharedPrefetcher<A> {
+    /// `shards` shards, each a prefetcher returned by `build`, for example
+    /// to set predictors or the delivery policy per shard.
+    pub fn new(shards: usize, mut build: impl FnMut() -> PredictivePrefetcher<A>) -> Self {
+        SharedPrefetcher {
+            shards: (0..shards.max(1)).map(|_| Mutex::new(build())).collect(),
+        }
+    }
+
+    /// `shards` shards with the given configuration, or the first invalid
+    /// value found in it.
+    pub fn from_config(config: PrefetcherConfig, shards: usize) -> Result<Self, ConfigError> {
+        config.validate()?;
+        Ok(Self::new(shards, || {
+            PredictivePrefetcher::from_config(config.clone()).expect("configuration was validated")
+        }))
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn lock(shard: &Mutex<PredictivePrefetcher<A>>) -> MutexGuard<'_, PredictivePrefetcher<A>> {
+        shard.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn shard_index(&self, stream_id: u64) -> usize {
+        // Fibonacci hashing, so consecutive stream ids land on different shards
+        let hash = stream_id.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32;
+        hash as usize % self.shards.len()
+    }
+
+    fn shard(&self, stream_id: u64) -> MutexGuard<'_, PredictivePrefetcher<A>> {
+        Self::lock(&self.shards[self.shard_index(stream_id)])
+    }
+
+    /// Record an access to a stream and return the predicted next addresses.
+    /// Only the stream's shard is locked, and only while the access is
+    /// processed; prediction batches go to the shard's subscribers without
+    /// waiting, as with `PredictivePrefetcher::observe`.
+    pub fn access(&self, stream_id: u64, address: A) -> Vec<A> {
+        self.shard(stream_id).observe(stream_id, address)
+    }
+
+    /// `access` for a batch of `(stream_id, address)` pairs, returning the
+    /// predictions made after each access. Shards are processed in parallel
+    /// on the rayon thread pool, each locked once for all of its accesses;
+    /// accesses of the same shard are applied in batch order.
+    pub fn access_batch(&self, accesses: &[(u64, A)]) -> Vec<Vec<A>> {
+        let mut by_shard = vec![Vec::new(); self.shards.len()];
+        for (index, &(stream_id, _)) in accesses.iter().enumerate() {
+            by_shard[self.shard_index(stream_id)].push(index);
+        }
+
+        let processed: Vec<Vec<(usize, Vec<A>)>> = by_shard
+            .into_par_iter()
+            .enumerate()
+            .filter(|(_, indices)| !indices.is_empty())
+            .map(|(shard, indices)| {
+                let mut prefetcher = Self::lock(&self.shards[shard]);
+                indices
+                    .into_iter()
+                    .map(|index| {
+                        let (stream_id, address) = accesses[index];
+                        (index, prefetcher.observe(stream_id, address))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut predictions = vec![Vec::new(); accesses.len()];
+        for (index, prediction) in processed.into_iter().flatten() {
+            predictions[index] = prediction;
+        }
+        predictions
+    }
+
+    /// Run `f` on the shard holding `stream_id`, e.g. to query its prefetch
+    /// distance or subscribe to its predictions. Accesses to that shard
+    /// wait until `f` returns.
+    pub fn with_shard<R>(&self, stream_id: u64, f: impl FnOnce(&mut PredictivePrefetcher<A>) -> R) -> R {
+        f(&mut self.shard(stream_id))
+    }
+
+    fn sum(&self, count: impl Fn(&PredictivePrefetcher<A>) -> usize) -> usize {
+        self.shards.iter().map(|shard| count(&Self::lock(shard))).sum()
+    }
+
+    /// Number of patterns held by all shards.
+    pub fn pattern_count(&self) -> usize {
+        self.sum(PredictivePrefetcher::pattern_count)
+    }
+
+    /// Number of streams tracked by all shards.
+    pub fn stream_count(&self) -> usize {
+        self.sum(PredictivePrefetcher::stream_count)
+    }
+
+    /// Number of issued prefetches not yet consumed or expired.
+    pub fn outstanding_count(&self) -> usize {
+        self.sum(PredictivePrefetcher::outstanding_count)
+    }
+
+    /// Prefetch statistics summed over all shards. Shards are locked one at
+    /// a time, so the counters may straddle concurrent accesses.
+    pub fn stats(&self) -> PrefetchStats {
+        let mut stats = PrefetchStats::default();
+        for shard in &self.shards {
+            stats.merge(&Self::lock(shard).stats());
+        }
+        stats
+    }
+
+    /// Reset the statistics of every shard.
+    pub fn reset_stats(&self) {
+        for shard in &self.shards {
+            Self::lock(shard).reset_stats();
+        }
+    }
+
+    /// `get_stats` summed over all shards.
+    pub fn get_stats(&self) -> (u32, u32, f64) {
+        let (hits, misses) = self.shards.iter().fold((0, 0), |(hits, misses), shard| {
+            let (shard_hits, shard_misses, _) = Self::lock(shard).get_stats();
+            (hits + shard_hits, misses + shard_misses)
+        });
+        let accuracy = if hits + misses > 0 {
+            hits as f64 / (hits + misses) as f64
+        } else {
+            0.0
+        };
+        (hits, misses, accuracy)
+    }
+}
@@ -0,0 +1,295 @@
+//! Snapshots of a prefetcher's learned state, so a restarted service does
+//! not have to warm up again.
+//!
+//! A snapshot holds the configuration, the per-stream histories, the pattern
+//! table with its confidences, the prefetch distances and degrees, the
+//! perceptron, the outstanding prefetches, the statistics and any state the
+//! predictors choose to save (the Markov chains of the built-in set).
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::address::Address;
+use crate::config::PrefetcherConfig;
+use crate::pattern_table::EvictionPolicy;
+use crate::predictor::SelectionMode;
+use crate::prefetcher::{AccessPattern, Lookahead, PatternType, PredictivePrefetcher};
+use crate::stats::{PatternStats, PrefetchStats};
+
+/// Version of the snapshot layout written by `save`. `load` rejects every
+/// other version.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 4] = b"MLPS";
+const JSON_FORMAT: &str = "ml-prefetcher-snapshot";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SnapshotFormat {
+    /// The magic bytes `MLPS`, the version as a little-endian `u32` and a
+    /// bincode payload.
+    #[default]
+    Binary,
+    /// A JSON object with `format`, `version` and `state` fields.
+    Json,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The snapshot could not be encoded or decoded.
+    Encoding(String),
+    /// The snapshot was written with another layout version.
+    Version { found: u32, supported: u32 },
+    /// The snapshot was taken from a prefetcher with another address type,
+    /// set of predictors or detection settings, or holds an invalid
+    /// configuration.
+    Incompatible(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "snapshot I/O error: {}", err),
+            SnapshotError::Encoding(message) => write!(f, "malformed snapshot: {}", message),
+            SnapshotError::Version { found, supported } => write!(
+                f,
+                "snapshot version {} is not supported (expected version {})",
+                found, supported
+            ),
+            SnapshotError::Incompatible(message) => write!(f, "incompatible snapshot: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+fn encoding(err: impl fmt::Display) -> SnapshotError {
+    SnapshotError::Encoding(err.to_string())
+}
+
+/// Width and signedness of an address type. Unlike the type's name, these do
+/// not change between compiler versions; types of the same width and
+/// signedness, such as `u64` and a 64-bit `usize`, share a tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct AddressType {
+    bits: u32,
+    signed: bool,
+}
+
+impl AddressType {
+    pub fn of<A: Address>() -> Self {
+        AddressType {
+            bits: std::mem::size_of::<A>() as u32 * 8,
+            signed: A::min_value() < A::zero(),
+        }
+    }
+}
+
+impl fmt::Display for AddressType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", if self.signed { 'i' } else { 'u' }, self.bits)
+    }
+}
+
+// Addresses are stored as their bit patterns, so the layout does not depend
+// on the address type; `address_type` guards against mixing types.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PrefetcherState {
+    pub address_type: AddressType,
+    pub config: PrefetcherConfig,
+    pub selection: SelectionMode,
+    pub max_streams: usize,
+    pub min_lead: u64,
+    pub clock: u64,
+    pub hits: u32,
+    pub misses: u32,
+    pub stream_evictions: u64,
+    pub dropped_batches: u64,
+    pub stats: StatsState,
+    pub streams: Vec<StreamSnapshot>,
+    pub patterns: PatternTableState,
+    pub lookahead: Vec<(u64, PatternType, Lookahead)>,
+    pub perceptron: PerceptronState,
+    pub outstanding: Vec<OutstandingEntry>,
+    pub predictors: Vec<PredictorState>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct StreamSnapshot {
+    pub stream_id: u64,
+    pub history: Vec<u64>,
+    pub last_access: u64,
+    pub proposals: Vec<Vec<u64>>,
+    pub accuracy: Vec<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PatternTableState {
+    pub capacity: usize,
+    pub policy: EvictionPolicy,
+    pub evictions: u64,
+    pub clock: u64,
+    pub entries: Vec<PatternEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PatternEntry {
+    pub stream_id: u64,
+    pub trigger: u64,
+    pub last_access: u64,
+    pub pattern: AccessPattern,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PerceptronState {
+    pub weights: Vec<i32>,
+    pub pending: Vec<(u64, Vec<usize>)>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct OutstandingEntry {
+    pub address: u64,
+    pub stream_id: u64,
+    pub trigger: u64,
+    pub pattern_type: PatternType,
+    pub source: Option<usize>,
+    pub issued_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PredictorState {
+    pub pattern_type: PatternType,
+    pub state: Option<Vec<u8>>,
+}
+
+// `PrefetchStats::by_pattern` as a list, since JSON map keys must be strings
+#[derive(Serialize, Deserialize)]
+pub(crate) struct StatsState {
+    demand_accesses: u64,
+    prefetches_issued: u64,
+    useful_prefetches: u64,
+    useless_prefetches: u64,
+    late_prefetches: u64,
+    by_pattern: Vec<(PatternType, PatternStats)>,
+}
+
+impl From<&PrefetchStats> for StatsState {
+    fn from(stats: &PrefetchStats) -> Self {
+        StatsState {
+            demand_accesses: stats.demand_accesses,
+            prefetches_issued: stats.prefetches_issued,
+            useful_prefetches: stats.useful_prefetches,
+            useless_prefetches: stats.useless_prefetches,
+            late_prefetches: stats.late_prefetches,
+            by_pattern: stats.by_pattern.iter().map(|(pattern, counters)| (pattern.clone(), *counters)).collect(),
+        }
+    }
+}
+
+impl From<StatsState> for PrefetchStats {
+    fn from(state: StatsState) -> Self {
+        PrefetchStats {
+            demand_accesses: state.demand_accesses,
+            prefetches_issued: state.prefetches_issued,
+            useful_prefetches: state.useful_prefetches,
+            useless_prefetches: state.useless_prefetches,
+            late_prefetches: state.late_prefetches,
+            by_pattern: state.by_pattern.into_iter().collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonSnapshot<T> {
+    format: String,
+    version: u32,
+    state: T,
+}
+
+fn check_version(found: u32) -> Result<(), SnapshotError> {
+    if found == SNAPSHOT_VERSION {
+        Ok(())
+    } else {
+        Err(SnapshotError::Version { found, supported: SNAPSHOT_VERSION })
+    }
+}
+
+fn decode(mut reader: impl Read, format: SnapshotFormat) -> Result<PrefetcherState, SnapshotError> {
+    match format {
+        SnapshotFormat::Binary => {
+            let mut header = [0u8; 8];
+            reader.read_exact(&mut header)?;
+            if &header[..4] != MAGIC {
+                return Err(SnapshotError::Encoding("not a binary prefetcher snapshot".to_string()));
+            }
+            check_version(u32::from_le_bytes([header[4], header[5], header[6], header[7]]))?;
+            bincode::deserialize_from(reader).map_err(encoding)
+        }
+        SnapshotFormat::Json => {
+            // Check the envelope before interpreting the state
+            let snapshot: JsonSnapshot<serde_json::Value> = serde_json::from_reader(reader).map_err(encoding)?;
+            if snapshot.format != JSON_FORMAT {
+                return Err(SnapshotError::Encoding(format!("unknown snapshot format {:?}", snapshot.format)));
+            }
+            check_version(snapshot.version)?;
+            serde_json::from_value(snapshot.state).map_err(encoding)
+        }
+    }
+}
+
+impl<A: Address> PredictivePrefetcher<A> {
+    /// Write the learned state of the prefetcher to `writer`.
+    pub fn save<W: Write>(&self, mut writer: W, format: SnapshotFormat) -> Result<(), SnapshotError> {
+        let state = self.snapshot_state(AddressType::of::<A>());
+        match format {
+            SnapshotFormat::Binary => {
+                writer.write_all(MAGIC)?;
+                writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+                bincode::serialize_into(&mut writer, &state).map_err(encoding)?;
+            }
+            SnapshotFormat::Json => {
+                let snapshot = JsonSnapshot { format: JSON_FORMAT.to_string(), version: SNAPSHOT_VERSION, state };
+                serde_json::to_writer(&mut writer, &snapshot).map_err(encoding)?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Replace the learned state and configuration of the prefetcher with a
+    /// snapshot written by `save`.
+    ///
+    /// The prefetcher must use the same address type, the same list of
+    /// predictors and the same detection ratios and region size as the one
+    /// that was saved, since its predictors are not rebuilt; builder settings
+    /// that are not learned (subscribers, delivery policy, outstanding buffer
+    /// size) are kept. Nothing is changed if the snapshot is of another
+    /// version, address type, predictor list or detection settings, or holds
+    /// an invalid configuration.
+    pub fn load<R: Read>(&mut self, reader: R, format: SnapshotFormat) -> Result<(), SnapshotError> {
+        let state = decode(reader, format)?;
+        let address_type = AddressType::of::<A>();
+        if state.address_type != address_type {
+            return Err(SnapshotError::Incompatible(format!(
+                "snapshot holds {} addresses, prefetcher uses {}",
+                state.address_type, address_type
+            )));
+        }
+        self.restore_state(state)
+    }
+}
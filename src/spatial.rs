@@ -0,0 +1,231 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::address::Address;
+use crate::config::PrefetcherConfig;
+use crate::predictor::{AccessContext, Candidate, Predictor};
+use crate::prefetcher::PatternType;
+
+const ACTIVE_REGIONS: usize = 32;
+const MAX_FOOTPRINTS: usize = 1024;
+
+/// Offsets touched within one region, one bit per address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Footprint(Vec<u64>);
+
+impl Footprint {
+    fn new(region_size: u64) -> Self {
+        Footprint(vec![0; region_size.div_ceil(64) as usize])
+    }
+
+    fn insert(&mut self, offset: u64) {
+        self.0[(offset / 64) as usize] |= 1 << (offset % 64);
+    }
+
+    fn contains(&self, offset: u64) -> bool {
+        self.0[(offset / 64) as usize] & (1 << (offset % 64)) != 0
+    }
+
+    fn len(&self) -> u32 {
+        self.0.iter().map(|word| word.count_ones()).sum()
+    }
+
+    fn offsets(&self) -> impl Iterator<Item = u64> + '_ {
+        self.0.iter().enumerate().flat_map(|(index, &word)| {
+            (0..64).filter(move |bit| word & (1 << bit) != 0).map(move |bit| index as u64 * 64 + bit)
+        })
+    }
+}
+
+// A region being recorded: everything touched since its trigger access, and
+// the footprint predicted at the trigger if one was learned
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Generation {
+    stream_id: u64,
+    trigger_offset: u64,
+    touched: Footprint,
+    predicted: Option<Footprint>,
+    last_access: u64,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SpatialState {
+    region_size: u64,
+    clock: u64,
+    active: Vec<(u64, Generation)>,
+    footprints: Vec<((u64, u64), Footprint)>,
+}
+
+/// Spatial memory streaming: recurring footprints within fixed-size regions.
+///
+/// The first access to a region (the trigger) starts a generation that
+/// records every offset the region's accesses touch. When the generation
+/// ends, because newer regions push it out of the 32 active ones, its
+/// footprint is learned for the stream and the trigger's offset within the
+/// region. The next trigger with the same stream and offset, in any region,
+/// predicts that footprint: each access to the region proposes the predicted
+/// offsets not touched yet, nearest first.
+pub struct SpatialPredictor {
+    region_size: u64,
+    clock: u64,
+    active: HashMap<u64, Generation>,
+    footprints: HashMap<(u64, u64), Footprint>,
+    insertion_order: VecDeque<(u64, u64)>,
+}
+
+impl SpatialPredictor {
+    /// Regions of `region_size` addresses; with 64-byte cache line
+    /// addresses, 64 covers a 4 KiB page. Sizes are clamped to between 1 and
+    /// `PrefetcherConfig::MAX_REGION_SIZE`.
+    pub fn new(region_size: usize) -> Self {
+        SpatialPredictor {
+            region_size: region_size.clamp(1, PrefetcherConfig::MAX_REGION_SIZE) as u64,
+            clock: 0,
+            active: HashMap::new(),
+            footprints: HashMap::new(),
+            insertion_order: VecDeque::new(),
+        }
+    }
+
+    pub fn region_size(&self) -> usize {
+        self.region_size as usize
+    }
+
+    fn learn(&mut self, generation: Generation) {
+        // A lone trigger has nothing to prefetch
+        if generation.touched.len() < 2 {
+            return;
+        }
+        let key = (generation.stream_id, generation.trigger_offset);
+        if !self.footprints.contains_key(&key) {
+            if self.footprints.len() >= MAX_FOOTPRINTS {
+                if let Some(oldest) = self.insertion_order.pop_front() {
+                    self.footprints.remove(&oldest);
+                }
+            }
+            self.insertion_order.push_back(key);
+        }
+        self.footprints.insert(key, generation.touched);
+    }
+}
+
+impl Default for SpatialPredictor {
+    fn default() -> Self {
+        SpatialPredictor::new(64)
+    }
+}
+
+impl<A: Address> Predictor<A> for SpatialPredictor {
+    fn pattern_type(&self) -> PatternType {
+        PatternType::Spatial
+    }
+
+    fn observe(&mut self, access: &AccessContext<A>) {
+        self.clock += 1;
+        let bits = access.address.to_bits();
+        let (region, offset) = (bits / self.region_size, bits % self.region_size);
+        if let Some(generation) = self.active.get_mut(&region) {
+            generation.touched.insert(offset);
+            generation.last_access = self.clock;
+            return;
+        }
+
+        // Trigger access: end the oldest generation if all slots are taken
+        if self.active.len() >= ACTIVE_REGIONS {
+            let oldest = self.active.iter()
+                .min_by_key(|(_, generation)| generation.last_access)
+                .map(|(&region, _)| region);
+            if let Some(generation) = oldest.and_then(|region| self.active.remove(&region)) {
+                self.learn(generation);
+            }
+        }
+        let mut touched = Footprint::new(self.region_size);
+        touched.insert(offset);
+        let predicted = self.footprints.get(&(access.stream_id, offset)).cloned();
+        self.active.insert(region, Generation {
+            stream_id: access.stream_id,
+            trigger_offset: offset,
+            touched,
+            predicted,
+            last_access: self.clock,
+        });
+    }
+
+    fn predict(&mut self, access: &AccessContext<A>, max_candidates: usize) -> Vec<Candidate<A>> {
+        let bits = access.address.to_bits();
+        let (region, offset) = (bits / self.region_size, bits % self.region_size);
+        let Some(generation) = self.active.get(&region) else {
+            return Vec::new();
+        };
+        let Some(predicted) = &generation.predicted else {
+            return Vec::new();
+        };
+
+        // Share of this generation's accesses the footprint foresaw
+        let touched = generation.touched.len();
+        let confirmed = generation.touched.offsets().filter(|&o| predicted.contains(o)).count() as u32;
+        let confidence = confirmed as f64 / touched as f64;
+
+        let mut remaining: Vec<u64> = predicted.offsets().filter(|&o| !generation.touched.contains(o)).collect();
+        // Nearest first, ahead of the access before behind it
+        remaining.sort_by_key(|&o| (o.abs_diff(offset), o < offset));
+        let base = region * self.region_size;
+        remaining
+            .into_iter()
+            .take(max_candidates)
+            .map(|o| Candidate { address: A::from_bits(base + o), confidence })
+            .collect()
+    }
+
+    fn forget_stream(&mut self, stream_id: u64) {
+        self.active.retain(|_, generation| generation.stream_id != stream_id);
+        self.footprints.retain(|&(stream, _), _| stream != stream_id);
+        self.insertion_order.retain(|&(stream, _)| stream != stream_id);
+    }
+
+    #[cfg(feature = "serde")]
+    fn save_state(&self) -> Option<Vec<u8>> {
+        let state = SpatialState {
+            region_size: self.region_size,
+            clock: self.clock,
+            active: self.active.iter().map(|(&region, generation)| (region, generation.clone())).collect(),
+            footprints: self.insertion_order
+                .iter()
+                .filter_map(|key| Some((*key, self.footprints.get(key)?.clone())))
+                .collect(),
+        };
+        bincode::serialize(&state).ok()
+    }
+
+    #[cfg(feature = "serde")]
+    fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        let state: SpatialState = bincode::deserialize(state).map_err(|err| err.to_string())?;
+        if state.region_size != self.region_size {
+            return Err(format!(
+                "footprints of {}-address regions, predictor uses {}",
+                state.region_size, self.region_size
+            ));
+        }
+        let words = self.region_size.div_ceil(64) as usize;
+        let fits = |footprint: &Footprint| footprint.0.len() == words;
+        let footprints_fit = state.footprints.iter().all(|(_, footprint)| fits(footprint));
+        let active_fit = state.active.iter().all(|(_, generation)| {
+            fits(&generation.touched) && generation.predicted.as_ref().is_none_or(fits)
+        });
+        if !footprints_fit || !active_fit {
+            return Err("footprint does not fit the region size".to_string());
+        }
+
+        self.clock = state.clock;
+        self.active = state.active.into_iter().collect();
+        self.footprints.clear();
+        self.insertion_order.clear();
+        for (key, footprint) in state.footprints {
+            self.insertion_order.push_back(key);
+            self.footprints.insert(key, footprint);
+        }
+        Ok(())
+    }
+}
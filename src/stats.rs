@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use crate::prefetcher::PatternType;
+
+/// Prefetch counters for a single pattern type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PatternStats {
+    pub issued: u64,
+    pub useful: u64,
+    pub useless: u64,
+    pub late: u64,
+}
+
+/// Standard prefetcher metrics.
+///
+/// A prefetch is *useful* when a demand access consumes it while it is still
+/// outstanding and *useless* when it expires unused. Useful prefetches that
+/// were consumed with less lead than the configured minimum are additionally
+/// counted as *late*.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PrefetchStats {
+    pub demand_accesses: u64,
+    pub prefetches_issued: u64,
+    pub useful_prefetches: u64,
+    pub useless_prefetches: u64,
+    pub late_prefetches: u64,
+    pub by_pattern: HashMap<PatternType, PatternStats>,
+}
+
+fn ratio(numerator: u64, denominator: u64) -> f64 {
+    if denominator > 0 {
+        numerator as f64 / denominator as f64
+    } else {
+        0.0
+    }
+}
+
+impl PrefetchStats {
+    /// Fraction of issued prefetches that were used.
+    pub fn accuracy(&self) -> f64 {
+        ratio(self.useful_prefetches, self.prefetches_issued)
+    }
+
+    /// Fraction of demand accesses that were covered by a prefetch.
+    pub fn coverage(&self) -> f64 {
+        ratio(self.useful_prefetches, self.demand_accesses)
+    }
+
+    /// Fraction of useful prefetches that arrived late.
+    pub fn lateness(&self) -> f64 {
+        ratio(self.late_prefetches, self.useful_prefetches)
+    }
+
+    /// Demand accesses that no prefetch covered.
+    pub fn uncovered(&self) -> u64 {
+        self.demand_accesses.saturating_sub(self.useful_prefetches)
+    }
+
+    pub fn reset(&mut self) {
+        *self = PrefetchStats::default();
+    }
+
+    /// Add the counters of `other`, e.g. of another shard.
+    pub(crate) fn merge(&mut self, other: &PrefetchStats) {
+        self.demand_accesses += other.demand_accesses;
+        self.prefetches_issued += other.prefetches_issued;
+        self.useful_prefetches += other.useful_prefetches;
+        self.useless_prefetches += other.useless_prefetches;
+        self.late_prefetches += other.late_prefetches;
+        for (pattern_type, counters) in &other.by_pattern {
+            let merged = self.pattern(pattern_type);
+            merged.issued += counters.issued;
+            merged.useful += counters.useful;
+            merged.useless += counters.useless;
+            merged.late += counters.late;
+        }
+    }
+
+    pub(crate) fn record_demand(&mut self) {
+        self.demand_accesses += 1;
+    }
+
+    pub(crate) fn record_issued(&mut self, pattern_type: &PatternType) {
+        self.prefetches_issued += 1;
+        self.pattern(pattern_type).issued += 1;
+    }
+
+    pub(crate) fn record_useful(&mut self, pattern_type: &PatternType, late: bool) {
+        self.useful_prefetches += 1;
+        let pattern = self.pattern(pattern_type);
+        pattern.useful += 1;
+        if late {
+            pattern.late += 1;
+            self.late_prefetches += 1;
+        }
+    }
+
+    pub(crate) fn record_useless(&mut self, pattern_type: &PatternType) {
+        self.useless_prefetches += 1;
+        self.pattern(pattern_type).useless += 1;
+    }
+
+    fn pattern(&mut self, pattern_type: &PatternType) -> &mut PatternStats {
+        self.by_pattern.entry(pattern_type.clone()).or_default()
+    }
+}
@@ -0,0 +1,572 @@
+//! Reading recorded address traces and replaying them through a prefetcher.
+//!
+//! The following formats are supported:
+//!
+//! - `Text`: one address per line, decimal or `0x`-prefixed hex. Empty lines
+//!   and lines starting with `#` are skipped.
+//! - `Csv`: `timestamp,pc,address,rw` columns, with an optional header line.
+//!   `rw` is `r`/`read`/`0` or `w`/`write`/`1`.
+//! - `Binary`: the magic bytes `MLPT`, a version byte, then one 25 byte
+//!   record per access: little-endian `u64` timestamp, pc and address, and
+//!   a kind byte (0 read, 1 write). See `write_binary`.
+//! - `ChampSim`: uncompressed ChampSim instruction traces (64 byte
+//!   `input_instr` records). Every source memory operand becomes a read and
+//!   every destination operand a write, tagged with the instruction pointer
+//!   and numbered by instruction.
+//! - `Lackey`: output of `valgrind --tool=lackey --trace-mem=yes`. Loads
+//!   (`L`) are reads, stores (`S`) and modifies (`M`) writes, each tagged
+//!   with the preceding instruction (`I`) address. Valgrind's `==` messages
+//!   are skipped.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::address::Address;
+use crate::prefetcher::{PatternType, PredictivePrefetcher, DEFAULT_STREAM};
+use crate::stats::PrefetchStats;
+
+const BINARY_MAGIC: &[u8; 4] = b"MLPT";
+const BINARY_VERSION: u8 = 1;
+const BINARY_RECORD_SIZE: usize = 25;
+const CHAMPSIM_RECORD_SIZE: usize = 64;
+// Offsets of the memory operands in a ChampSim `input_instr`
+const CHAMPSIM_DESTINATIONS: std::ops::Range<usize> = 16..32;
+const CHAMPSIM_SOURCES: std::ops::Range<usize> = 32..64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AccessKind {
+    #[default]
+    Read,
+    Write,
+}
+
+/// A single memory access of a trace. Formats without timestamps number
+/// records from zero; formats without a program counter report 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TraceRecord {
+    pub timestamp: u64,
+    pub pc: u64,
+    pub address: u64,
+    pub kind: AccessKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TraceFormat {
+    Text,
+    Csv,
+    Binary,
+    ChampSim,
+    Lackey,
+}
+
+impl TraceFormat {
+    /// Guess the format from a file extension: `.csv` is CSV, `.bin` and
+    /// `.mlpt` are binary, `.champsimtrace` is ChampSim, `.lackey` is Lackey
+    /// output, anything else is text.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let extension = path.as_ref().extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        match extension.to_ascii_lowercase().as_str() {
+            "csv" => TraceFormat::Csv,
+            "bin" | "mlpt" => TraceFormat::Binary,
+            "champsimtrace" => TraceFormat::ChampSim,
+            "lackey" => TraceFormat::Lackey,
+            _ => TraceFormat::Text,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    /// A malformed record. `record` is the 1-based line number for text
+    /// formats and the 1-based record number for binary ones (0 for the
+    /// header).
+    Parse { record: u64, message: String },
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceError::Io(err) => write!(f, "trace I/O error: {}", err),
+            TraceError::Parse { record, message } => write!(f, "record {}: {}", record, message),
+        }
+    }
+}
+
+impl std::error::Error for TraceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TraceError::Io(err) => Some(err),
+            TraceError::Parse { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for TraceError {
+    fn from(err: io::Error) -> Self {
+        TraceError::Io(err)
+    }
+}
+
+/// Parse a decimal or `0x`-prefixed hex number.
+fn parse_number(field: &str) -> Option<u64> {
+    match field.strip_prefix("0x").or_else(|| field.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => field.parse().ok(),
+    }
+}
+
+fn parse_kind(field: &str) -> Option<AccessKind> {
+    match field.to_ascii_lowercase().as_str() {
+        "r" | "read" | "0" => Some(AccessKind::Read),
+        "w" | "write" | "1" => Some(AccessKind::Write),
+        _ => None,
+    }
+}
+
+fn parse_csv(fields: &[&str]) -> Result<TraceRecord, String> {
+    let [timestamp, pc, address, kind] = fields else {
+        return Err(format!("expected 4 columns, found {}", fields.len()));
+    };
+    let number = |field: &str, name: &str| {
+        parse_number(field).ok_or_else(|| format!("invalid {} {:?}", name, field))
+    };
+    Ok(TraceRecord {
+        timestamp: number(timestamp, "timestamp")?,
+        pc: number(pc, "pc")?,
+        address: number(address, "address")?,
+        kind: parse_kind(kind).ok_or_else(|| format!("invalid access kind {:?}", kind))?,
+    })
+}
+
+/// Streaming reader over the records of a trace.
+pub struct TraceReader<R: BufRead> {
+    reader: R,
+    format: TraceFormat,
+    // Lines read for text formats, records read for binary ones
+    position: u64,
+    records: u64,
+    started: bool,
+    line: String,
+    // Instruction formats: accesses of the current instruction not yet
+    // returned, the current instruction pointer and instructions seen
+    pending: VecDeque<TraceRecord>,
+    ip: u64,
+    instructions: u64,
+}
+
+impl TraceReader<BufReader<File>> {
+    /// Open a trace file, guessing its format from the extension.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let format = TraceFormat::from_path(&path);
+        Self::open_with_format(path, format)
+    }
+
+    pub fn open_with_format(path: impl AsRef<Path>, format: TraceFormat) -> io::Result<Self> {
+        Ok(TraceReader::new(BufReader::new(File::open(path)?), format))
+    }
+}
+
+impl<R: BufRead> TraceReader<R> {
+    pub fn new(reader: R, format: TraceFormat) -> Self {
+        TraceReader {
+            reader,
+            format,
+            position: 0,
+            records: 0,
+            started: false,
+            line: String::new(),
+            pending: VecDeque::new(),
+            ip: 0,
+            instructions: 0,
+        }
+    }
+
+    pub fn format(&self) -> TraceFormat {
+        self.format
+    }
+
+    fn error(&self, message: impl Into<String>) -> TraceError {
+        TraceError::Parse { record: self.position, message: message.into() }
+    }
+
+    /// Read the next non-empty, non-comment line into `self.line`. Returns
+    /// `false` at the end of the trace.
+    fn next_line(&mut self) -> Result<bool, TraceError> {
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Ok(false);
+            }
+            self.position += 1;
+            let trimmed = self.line.trim();
+            if !trimmed.is_empty() && !trimmed.starts_with('#') {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn next_text(&mut self) -> Result<Option<TraceRecord>, TraceError> {
+        if !self.next_line()? {
+            return Ok(None);
+        }
+        let line = self.line.trim();
+        let address = parse_number(line).ok_or_else(|| self.error(format!("invalid address {:?}", line)))?;
+        Ok(Some(TraceRecord { timestamp: self.records, address, ..TraceRecord::default() }))
+    }
+
+    fn next_csv(&mut self) -> Result<Option<TraceRecord>, TraceError> {
+        loop {
+            if !self.next_line()? {
+                return Ok(None);
+            }
+            let fields: Vec<&str> = self.line.trim().split(',').map(str::trim).collect();
+            let header = !self.started && fields.first().is_some_and(|f| parse_number(f).is_none());
+            self.started = true;
+            if header {
+                continue;
+            }
+            return parse_csv(&fields).map(Some).map_err(|message| self.error(message));
+        }
+    }
+
+    /// Fill `buf` from the reader. Returns the number of bytes read, which
+    /// is less than `buf.len()` only at the end of the input.
+    fn fill(&mut self, buf: &mut [u8]) -> Result<usize, TraceError> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.reader.read(&mut buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(filled)
+    }
+
+    fn next_binary(&mut self) -> Result<Option<TraceRecord>, TraceError> {
+        if !self.started {
+            self.started = true;
+            let mut header = [0u8; 5];
+            self.reader.read_exact(&mut header).map_err(|_| self.error("missing binary trace header"))?;
+            if &header[..4] != BINARY_MAGIC {
+                return Err(self.error("not a binary trace"));
+            }
+            if header[4] != BINARY_VERSION {
+                return Err(self.error(format!("unsupported binary trace version {}", header[4])));
+            }
+        }
+
+        let mut buf = [0u8; BINARY_RECORD_SIZE];
+        let filled = self.fill(&mut buf)?;
+        if filled == 0 {
+            return Ok(None);
+        }
+        self.position += 1;
+        if filled < buf.len() {
+            return Err(self.error("truncated record"));
+        }
+
+        let word = |i: usize| u64::from_le_bytes(buf[i * 8..i * 8 + 8].try_into().unwrap());
+        let kind = match buf[24] {
+            0 => AccessKind::Read,
+            1 => AccessKind::Write,
+            other => return Err(self.error(format!("invalid access kind {}", other))),
+        };
+        Ok(Some(TraceRecord { timestamp: word(0), pc: word(1), address: word(2), kind }))
+    }
+
+    fn next_champsim(&mut self) -> Result<Option<TraceRecord>, TraceError> {
+        while self.pending.is_empty() {
+            let mut buf = [0u8; CHAMPSIM_RECORD_SIZE];
+            let filled = self.fill(&mut buf)?;
+            if filled == 0 {
+                return Ok(None);
+            }
+            self.position += 1;
+            if filled < buf.len() {
+                return Err(self.error("truncated instruction"));
+            }
+
+            let ip = u64::from_le_bytes(buf[..8].try_into().unwrap());
+            let timestamp = self.position - 1;
+            let operands = |range: std::ops::Range<usize>, kind: AccessKind| {
+                buf[range]
+                    .chunks_exact(8)
+                    .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                    .filter(|&address| address != 0)
+                    .map(move |address| TraceRecord { timestamp, pc: ip, address, kind })
+                    .collect::<Vec<_>>()
+            };
+            self.pending.extend(operands(CHAMPSIM_SOURCES, AccessKind::Read));
+            self.pending.extend(operands(CHAMPSIM_DESTINATIONS, AccessKind::Write));
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    fn next_lackey(&mut self) -> Result<Option<TraceRecord>, TraceError> {
+        loop {
+            if !self.next_line()? {
+                return Ok(None);
+            }
+            let line = self.line.trim();
+            if line.starts_with("==") {
+                continue;
+            }
+            let (op, rest) = line.split_once(char::is_whitespace)
+                .ok_or_else(|| self.error(format!("invalid Lackey line {:?}", line)))?;
+            let address = rest.trim().split(',').next().unwrap_or("");
+            let address = u64::from_str_radix(address, 16)
+                .map_err(|_| self.error(format!("invalid address {:?}", address)))?;
+            let kind = match op {
+                "I" => {
+                    self.ip = address;
+                    self.instructions += 1;
+                    continue;
+                }
+                "L" => AccessKind::Read,
+                "S" | "M" => AccessKind::Write,
+                _ => return Err(self.error(format!("unknown Lackey operation {:?}", op))),
+            };
+            let timestamp = self.instructions.saturating_sub(1);
+            return Ok(Some(TraceRecord { timestamp, pc: self.ip, address, kind }));
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = Result<TraceRecord, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.format {
+            TraceFormat::Text => self.next_text(),
+            TraceFormat::Csv => self.next_csv(),
+            TraceFormat::Binary => self.next_binary(),
+            TraceFormat::ChampSim => self.next_champsim(),
+            TraceFormat::Lackey => self.next_lackey(),
+        };
+        if let Ok(Some(_)) = record {
+            self.records += 1;
+        }
+        record.transpose()
+    }
+}
+
+/// Write `records` in the binary trace format.
+pub fn write_binary<'a, W: Write>(
+    mut writer: W,
+    records: impl IntoIterator<Item = &'a TraceRecord>,
+) -> io::Result<()> {
+    writer.write_all(BINARY_MAGIC)?;
+    writer.write_all(&[BINARY_VERSION])?;
+    for record in records {
+        writer.write_all(&record.timestamp.to_le_bytes())?;
+        writer.write_all(&record.pc.to_le_bytes())?;
+        writer.write_all(&record.address.to_le_bytes())?;
+        writer.write_all(&[record.kind as u8])?;
+    }
+    writer.flush()
+}
+
+/// How `replay_with` feeds records to the prefetcher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReplayOptions {
+    /// Addresses are divided by `line_size` before calling `access`, so the
+    /// prefetcher sees and predicts cache line numbers. 1 replays byte
+    /// addresses unchanged.
+    pub line_size: u64,
+    /// Use each record's program counter as the stream of the access.
+    /// Records without one share `DEFAULT_STREAM`.
+    pub per_pc: bool,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        ReplayOptions { line_size: 1, per_pc: true }
+    }
+}
+
+impl ReplayOptions {
+    /// Cache-line granularity with the given line size, for instruction
+    /// traces such as ChampSim or Lackey.
+    pub fn lines(line_size: u64) -> Self {
+        ReplayOptions { line_size, ..ReplayOptions::default() }
+    }
+
+    /// Address passed to the prefetcher for `record`.
+    pub fn address(&self, record: &TraceRecord) -> u64 {
+        record.address / self.line_size.max(1)
+    }
+
+    /// Stream passed to the prefetcher for `record`.
+    pub fn stream(&self, record: &TraceRecord) -> u64 {
+        if self.per_pc { record.pc } else { DEFAULT_STREAM }
+    }
+}
+
+/// Outcome of replaying a trace.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayReport {
+    pub records: u64,
+    pub reads: u64,
+    pub writes: u64,
+    /// Span of the record timestamps, which counts instructions for
+    /// ChampSim and Lackey traces and records for text traces.
+    pub instructions: u64,
+    /// Addresses returned by the prefetcher over the whole trace.
+    pub predictions: u64,
+    /// `get_stats` of the prefetcher after the replay.
+    pub hits: u32,
+    pub misses: u32,
+    pub accuracy: f64,
+    /// `stats` of the prefetcher after the replay.
+    pub stats: PrefetchStats,
+}
+
+/// Feed every record through `prefetcher` with the default options and
+/// collect its statistics.
+///
+/// Statistics are cumulative, so call `reset_stats` first to measure only
+/// this trace. Replay stops at the first malformed record.
+pub fn replay<A: Address>(
+    prefetcher: &mut PredictivePrefetcher<A>,
+    records: impl IntoIterator<Item = Result<TraceRecord, TraceError>>,
+) -> Result<ReplayReport, TraceError> {
+    replay_with(prefetcher, records, ReplayOptions::default())
+}
+
+/// `replay` with explicit options.
+pub fn replay_with<A: Address>(
+    prefetcher: &mut PredictivePrefetcher<A>,
+    records: impl IntoIterator<Item = Result<TraceRecord, TraceError>>,
+    options: ReplayOptions,
+) -> Result<ReplayReport, TraceError> {
+    replay_each(prefetcher, records, options, |_, _| {})
+}
+
+/// Replay driver shared with the cache simulator: `on_access` sees every
+/// record together with the addresses the prefetcher predicted for it.
+pub(crate) fn replay_each<A: Address>(
+    prefetcher: &mut PredictivePrefetcher<A>,
+    records: impl IntoIterator<Item = Result<TraceRecord, TraceError>>,
+    options: ReplayOptions,
+    mut on_access: impl FnMut(&TraceRecord, &[A]),
+) -> Result<ReplayReport, TraceError> {
+    let mut report = ReplayReport::default();
+    let mut first_timestamp = None;
+    for record in records {
+        let record = record?;
+        report.records += 1;
+        match record.kind {
+            AccessKind::Read => report.reads += 1,
+            AccessKind::Write => report.writes += 1,
+        }
+        let first = *first_timestamp.get_or_insert(record.timestamp);
+        report.instructions = report.instructions.max(record.timestamp.saturating_sub(first) + 1);
+
+        let address = A::from_bits(options.address(&record));
+        let predictions = prefetcher.observe(options.stream(&record), address);
+        report.predictions += predictions.len() as u64;
+        on_access(&record, &predictions);
+    }
+
+    (report.hits, report.misses, report.accuracy) = prefetcher.get_stats();
+    report.stats = prefetcher.stats();
+    Ok(report)
+}
+
+/// How `analyze` splits a trace into parts that are replayed independently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Partition {
+    /// One part per stream, as chosen by `ReplayOptions::stream`.
+    #[default]
+    Stream,
+    /// One part per aligned region of this many bytes. Accesses keep their
+    /// streams within the part.
+    Region(u64),
+}
+
+/// What `analyze` found in one part of a trace.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartProfile {
+    /// The stream, or the region number (address divided by region size).
+    pub key: u64,
+    pub accesses: u64,
+    /// Pattern behind most of the part's predictions; `Unknown` when no
+    /// pattern was recognised.
+    pub pattern_type: PatternType,
+    /// Accesses per pattern type the prefetcher predicted with, most
+    /// frequent first.
+    pub patterns: Vec<(PatternType, u64)>,
+    /// `stats` of the part's prefetcher after its replay.
+    pub stats: PrefetchStats,
+}
+
+/// Outcome of `analyze`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraceAnalysis {
+    pub records: u64,
+    /// Parts with the most accesses first.
+    pub parts: Vec<PartProfile>,
+}
+
+/// Split a trace into independent parts and replay each through its own
+/// prefetcher from `build`, in parallel on the rayon thread pool, to
+/// classify the access pattern of every stream or region.
+///
+/// The trace is read once into memory (16 bytes per record); the result does
+/// not depend on the number of threads. Reading stops at the first
+/// malformed record.
+pub fn analyze<A: Address>(
+    records: impl IntoIterator<Item = Result<TraceRecord, TraceError>>,
+    options: ReplayOptions,
+    partition: Partition,
+    build: impl Fn() -> PredictivePrefetcher<A> + Sync,
+) -> Result<TraceAnalysis, TraceError> {
+    let mut analysis = TraceAnalysis::default();
+    let mut parts: HashMap<u64, Vec<(u64, u64)>> = HashMap::new();
+    for record in records {
+        let record = record?;
+        analysis.records += 1;
+        let key = match partition {
+            Partition::Stream => options.stream(&record),
+            Partition::Region(size) => record.address / size.max(1),
+        };
+        parts.entry(key).or_default().push((options.stream(&record), options.address(&record)));
+    }
+
+    analysis.parts = parts
+        .into_par_iter()
+        .map(|(key, accesses)| {
+            let mut prefetcher = build();
+            // In order of first use, so that ties keep a deterministic order
+            let mut patterns: Vec<(PatternType, u64)> = Vec::new();
+            for &(stream_id, address) in &accesses {
+                let batch = prefetcher.process(stream_id, A::from_bits(address));
+                match patterns.iter_mut().find(|(pattern_type, _)| *pattern_type == batch.pattern_type) {
+                    Some((_, count)) => *count += 1,
+                    None => patterns.push((batch.pattern_type, 1)),
+                }
+            }
+            patterns.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+            let pattern_type = patterns.iter()
+                .find(|(pattern_type, _)| *pattern_type != PatternType::Unknown)
+                .map_or(PatternType::Unknown, |(pattern_type, _)| pattern_type.clone());
+            PartProfile {
+                key,
+                accesses: accesses.len() as u64,
+                pattern_type,
+                patterns,
+                stats: prefetcher.stats(),
+            }
+        })
+        .collect();
+    analysis.parts.sort_by(|a, b| b.accesses.cmp(&a.accesses).then(a.key.cmp(&b.key)));
+    Ok(analysis)
+}
@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use ml_prefetcher::cache::{simulate, Cache, CacheConfig, Replacement};
+    use ml_prefetcher::trace::{ReplayOptions, TraceRecord};
+    use ml_prefetcher::PredictivePrefetcher;
+
+    // A single set of two 64 byte lines
+    fn tiny(replacement: Replacement) -> CacheConfig {
+        CacheConfig { size: 128, associativity: 2, line_size: 64, replacement }
+    }
+
+    #[test]
+    fn test_lru_replacement() {
+        let mut cache = Cache::new(tiny(Replacement::Lru));
+        assert_eq!(cache.config().sets(), 1);
+        assert!(!cache.access(0));
+        assert!(!cache.access(64));
+        assert!(cache.access(8), "Same line as address 0");
+        assert!(!cache.access(128));
+
+        assert!(cache.contains(0));
+        assert!(!cache.contains(64), "Least recently used line should be evicted");
+        let stats = cache.stats();
+        assert_eq!((stats.accesses, stats.hits, stats.misses), (4, 1, 3));
+        assert_eq!(CacheConfig::default().sets(), 64);
+    }
+
+    #[test]
+    fn test_random_replacement() {
+        let config = CacheConfig { replacement: Replacement::Random, ..CacheConfig::default() };
+        let mut first = Cache::new(config);
+        let mut second = Cache::new(config);
+        for i in 0..10_000u64 {
+            let address = (i * 7919) % 100_000 * 64;
+            assert_eq!(first.access(address), second.access(address), "Random replacement must be reproducible");
+        }
+        let resident = (0..100_000u64).filter(|&line| first.contains(line * 64)).count();
+        assert_eq!(resident, 512, "A full cache holds exactly its capacity");
+    }
+
+    #[test]
+    fn test_prefetch_accounting() {
+        let mut cache = Cache::new(tiny(Replacement::Lru));
+        assert!(cache.prefetch(0));
+        assert!(!cache.prefetch(32), "Line already cached");
+        assert!(cache.access(0));
+        cache.access(64);
+        // Evicts line 0, which a demand access used
+        assert!(cache.prefetch(128));
+        // Evicts line 64, then the unused prefetch of 128
+        cache.prefetch(192);
+        cache.prefetch(256);
+
+        let stats = cache.stats();
+        println!("Cache stats: {:?}", stats);
+        assert_eq!(stats.prefetch_fills, 4);
+        assert_eq!(stats.redundant_prefetches, 1);
+        assert_eq!(stats.useful_prefetches, 1);
+        assert_eq!(stats.prefetch_evictions, 2);
+        assert_eq!(stats.unused_prefetches, 1);
+    }
+
+    #[test]
+    fn test_simulate_streaming_trace() {
+        // One load per line streaming through 1 MiB, every other instruction
+        let records = (0..16_384u64).map(|i| Ok(TraceRecord {
+            timestamp: i * 2,
+            pc: 0x401000,
+            address: 0x1000_0000 + i * 64,
+            ..TraceRecord::default()
+        }));
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(8);
+        let report = simulate(&mut prefetcher, records, CacheConfig::default(), ReplayOptions::lines(64)).unwrap();
+        println!("Baseline: {:?}", report.baseline);
+        println!("Prefetching: {:?}", report.prefetching);
+
+        assert_eq!(report.baseline.misses, 16_384, "Every line misses without prefetching");
+        assert_eq!(report.replay.instructions, 32_767);
+        assert!((report.baseline_mpki() - 16_384_000.0 / 32_767.0).abs() < 1e-9);
+        assert!(report.miss_reduction() > 0.9, "Next-line prefetches should remove most misses, got {}", report.miss_reduction());
+        assert!(report.prefetching_mpki() < report.baseline_mpki());
+        // Every removed miss was served by a prefetched line
+        assert_eq!(report.prefetching.useful_prefetches, report.baseline.misses - report.prefetching.misses);
+        assert_eq!(report.baseline.prefetch_fills, 0);
+    }
+}
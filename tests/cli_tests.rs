@@ -0,0 +1,91 @@
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::process::{Command, Output};
+
+    // A CSV trace streaming through `lines` cache lines from one instruction
+    fn write_trace(name: &str, lines: u64) -> PathBuf {
+        let mut text = String::from("timestamp,pc,address,rw\n");
+        for i in 0..lines {
+            text.push_str(&format!("{},0x401000,{:#x},r\n", i * 2, 0x1000_0000 + i * 64));
+        }
+        let path = std::env::temp_dir().join(format!("ml-prefetcher-{}-{}.csv", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn run(args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_ml-prefetcher")).args(args).output().unwrap()
+    }
+
+    #[test]
+    fn test_cli_simulate_json() {
+        let trace = write_trace("simulate", 1024);
+        let output = run(&["simulate", trace.to_str().unwrap(), "--json"]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        println!("{}", stdout);
+        assert!(output.status.success());
+        assert!(stdout.trim().starts_with('{') && stdout.trim().ends_with('}'));
+        assert!(stdout.contains("\"records\":1024"));
+        assert!(stdout.contains("\"baseline_misses\":1024"));
+        std::fs::remove_file(trace).unwrap();
+    }
+
+    #[test]
+    fn test_cli_sweep_and_compare() {
+        let trace = write_trace("sweep", 256);
+        let path = trace.to_str().unwrap();
+
+        let output = run(&["sweep", path, "--history", "4,8", "--window", "2,4,8"]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        println!("{}", stdout);
+        assert!(output.status.success());
+        // A header plus one row per grid point
+        assert_eq!(stdout.lines().count(), 1 + 2 * 3);
+        assert!(stdout.contains("history=8,min-confidence=0.2,window=2"));
+
+        let output = run(&["compare", path, "--config", "history=4", "--config", "history=16,window=8", "--json"]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(output.status.success());
+        assert_eq!(stdout.matches("\"config\":").count(), 2);
+        assert!(stdout.contains("\"history_size\":16,\"min_confidence\":0.2,\"max_window_size\":8"));
+
+        // The built-in detectors against the best-offset baseline
+        let output = run(&["compare", path, "--config", "predictors=default", "--config", "predictors=best-offset"]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        println!("{}", stdout);
+        assert!(output.status.success());
+        assert!(stdout.contains("window=4,predictors=best-offset"));
+        let output = run(&["sweep", path, "--predictors", "default,best-offset", "--json"]);
+        assert_eq!(String::from_utf8(output.stdout).unwrap().matches("\"predictors\":").count(), 2);
+        assert!(!run(&["simulate", path, "--predictors", "best"]).status.success());
+
+        // Lists are only accepted by sweep, and compare needs a config
+        assert!(!run(&["simulate", path, "--history", "4,8"]).status.success());
+        assert!(!run(&["compare", path]).status.success());
+        assert!(!run(&["simulate", "/nonexistent/trace.csv"]).status.success());
+        std::fs::remove_file(trace).unwrap();
+    }
+
+    #[test]
+    fn test_cli_analyze() {
+        let trace = write_trace("analyze", 512);
+        let path = trace.to_str().unwrap();
+
+        let output = run(&["analyze", path]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        println!("{}", stdout);
+        assert!(output.status.success());
+        assert!(stdout.contains("0x401000") && stdout.contains("Sequential"));
+
+        let output = run(&["analyze", path, "--region", "4096", "--json"]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(output.status.success());
+        // 512 lines of 64 bytes span eight 4 KiB regions
+        assert_eq!(stdout.matches("\"key\":").count(), 8);
+
+        assert!(!run(&["simulate", path, "--region", "4096"]).status.success());
+        assert!(!run(&["analyze", path, "--region", "0"]).status.success());
+        std::fs::remove_file(trace).unwrap();
+    }
+}
@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod tests {
+    use ml_prefetcher::hierarchy::{simulate_hierarchy, Hierarchy, HierarchyConfig};
+    use ml_prefetcher::trace::{ReplayOptions, TraceRecord};
+    use ml_prefetcher::PredictivePrefetcher;
+
+    #[test]
+    fn test_demand_latency() {
+        let mut hierarchy = Hierarchy::new(HierarchyConfig::default());
+        assert_eq!(hierarchy.access(0x1000, 0), 200);
+        assert_eq!(hierarchy.access(0x1008, 10), 4, "Same line now hits L1");
+        // Back-to-back misses queue for the memory bus
+        assert_eq!(hierarchy.access(0x2000, 20), 200);
+        assert_eq!(hierarchy.access(0x3000, 20), 204);
+
+        let stats = hierarchy.stats();
+        assert_eq!(stats.level_hits, vec![1, 0, 0]);
+        assert_eq!(stats.memory_accesses, 3);
+        assert!((stats.amat() - 608.0 / 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_prefetch_timeliness() {
+        let mut hierarchy = Hierarchy::new(HierarchyConfig::default());
+        assert!(hierarchy.prefetch(0x1000, 0));
+        assert!(hierarchy.prefetch(0x2000, 0));
+        assert!(!hierarchy.prefetch(0x1010, 0), "Line already in flight");
+
+        // Arrives at 200, so an access at 100 waits for the rest
+        assert_eq!(hierarchy.access(0x1000, 100), 100 + 4);
+        // Arrived at 204, long before this access
+        assert_eq!(hierarchy.access(0x2000, 300), 4);
+
+        let stats = hierarchy.stats();
+        assert_eq!(stats.prefetches_issued, 2);
+        assert_eq!(stats.late_prefetches, 1);
+        assert_eq!(stats.timely_prefetches, 1);
+        assert_eq!(stats.memory_accesses, 0);
+    }
+
+    #[test]
+    fn test_simulate_hierarchy() {
+        // One load per line, 20 cycles apart
+        let records: Vec<TraceRecord> = (0..8192u64)
+            .map(|i| TraceRecord { timestamp: i * 20, pc: 0x401000, address: 0x1000_0000 + i * 64, ..TraceRecord::default() })
+            .collect();
+        let run = |memory_latency| {
+            let mut config = HierarchyConfig::default();
+            config.memory.latency = memory_latency;
+            let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(8);
+            let records = records.iter().copied().map(Ok);
+            simulate_hierarchy(&mut prefetcher, records, config, ReplayOptions::lines(64)).unwrap()
+        };
+
+        // Prefetches run two lines, 40 cycles, ahead of the demand stream
+        let slow = run(200);
+        let fast = run(30);
+        println!("Slow memory: {:?}\nFast memory: {:?}", slow.prefetching, fast.prefetching);
+        assert!((slow.baseline_amat() - 200.0).abs() < 1e-9, "Every line misses without prefetching");
+        assert!(slow.prefetching_amat() < slow.baseline_amat(), "Late prefetches still hide part of the latency");
+        assert!(slow.prefetching.late_prefetches > slow.prefetching.timely_prefetches);
+        assert!(fast.prefetching.timely_prefetches > fast.prefetching.late_prefetches);
+        assert!(fast.speedup() > slow.speedup());
+    }
+}
@@ -1,10 +1,39 @@
 #[cfg(test)]
 mod tests {
-    use ml_prefetcher::PredictivePrefetcher;
+    use ml_prefetcher::{
+        Address, AccessContext, BestOffsetPredictor, Candidate, DeliveryPolicy, EvictionPolicy, MarkovMode, MarkovPredictor, PatternType,
+        ConfigError, Predictor, PredictivePrefetcher, PrefetcherConfig, SelectionMode, SequentialPredictor, SpatialPredictor,
+        DEFAULT_STREAM,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    // Predicts the same offset in the next page, e.g. a per-page header walk
+    struct NextPagePredictor {
+        hits: Arc<AtomicUsize>,
+    }
+
+    impl Predictor<u64> for NextPagePredictor {
+        fn pattern_type(&self) -> PatternType {
+            PatternType::Custom("next-page".to_string())
+        }
+
+        fn predict(&mut self, access: &AccessContext<u64>, max_candidates: usize) -> Vec<Candidate<u64>> {
+            (1..=max_candidates as u64)
+                .map(|i| Candidate { address: access.address + i * 4096, confidence: 0.9 })
+                .collect()
+        }
+
+        fn feedback(&mut self, _stream_id: u64, _address: u64, hit: bool) {
+            if hit {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
 
     #[tokio::test]
     async fn test_new_prefetcher() {
-        let prefetcher = PredictivePrefetcher::new(4);
+        let prefetcher: PredictivePrefetcher = PredictivePrefetcher::new(4);
         let (hits, misses, accuracy) = prefetcher.get_stats();
         assert_eq!(hits, 0);
         assert_eq!(misses, 0);
@@ -31,7 +60,7 @@ mod tests {
                 any_predictions = true;
                 for &pred in &predictions {
                     assert!(pred > i, "Prediction should be greater than current access");
-                    assert!(pred <= i + 3, "Prediction should be within reasonable range");
+                    assert!(pred <= i + 4, "Prediction should be within reasonable range");
                 }
             }
         }
@@ -73,7 +102,7 @@ mod tests {
                         );
                     }
                     assert!(pred > i, "Prediction should be greater than current access");
-                    assert!(pred <= i + 6, "Prediction should be within reasonable range");
+                    assert!(pred <= i + 4 * stride, "Prediction should be within reasonable range");
                 }
             }
         }
@@ -173,4 +202,774 @@ mod tests {
         println!("\nFinal stats - Hits: {}, Misses: {}, Accuracy: {}", hits, misses, accuracy);
         assert!(accuracy >= 0.0, "Should maintain non-negative accuracy");
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_64bit_addresses() {
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4);
+        let base = 1u64 << 40;
+        let mut any_predictions = false;
+
+        for i in 0..8 {
+            let addr = base + i * 64;
+            let predictions = prefetcher.access(addr).await;
+            println!("Access: {:#x}, Predictions: {:x?}", addr, predictions);
+            if i < 2 {
+                continue;
+            }
+            for &pred in &predictions {
+                any_predictions = true;
+                assert!(pred > addr, "Prediction should be above the current access");
+                assert_eq!((pred - base) % 64, 0, "Prediction should keep the 64-byte stride");
+            }
+        }
+        assert!(any_predictions, "Should predict addresses above 2^31");
+    }
+
+    #[tokio::test]
+    async fn test_strided_near_address_space_end() {
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4);
+        let start = u64::MAX - 8 * 4096;
+
+        for i in 0..=8 {
+            let addr = start + i * 4096;
+            let predictions = prefetcher.access(addr).await;
+            println!("Access: {:#x}, Predictions: {:x?}", addr, predictions);
+            for &pred in &predictions {
+                assert!(pred > addr, "Predictions must not wrap past the end of the address space");
+            }
+        }
+    }
+
+    #[test]
+    fn test_address_arithmetic() {
+        assert_eq!(10u64.delta(14), -4);
+        assert_eq!(u64::MAX.checked_offset(1), None);
+        assert_eq!(0u32.checked_offset(-1), None);
+        assert_eq!(5usize.checked_offset(-2), Some(3));
+        assert_eq!(u64::MAX.wrapping_offset(2), 1);
+        assert_eq!(i64::MIN.checked_offset(-1), None);
+    }
+
+    #[tokio::test]
+    async fn test_perceptron_filters_random_access() {
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4);
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut issued = Vec::new();
+
+        for _ in 0..2000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let predictions = prefetcher.access(state % 1_000_000).await;
+            issued.push(predictions.len());
+        }
+
+        let early: usize = issued[..200].iter().sum();
+        let late: usize = issued[issued.len() - 200..].iter().sum();
+        println!("Issued early: {}, late: {}", early, late);
+        assert!(late < early / 2, "Perceptron should learn to reject useless random-access candidates");
+    }
+
+    #[tokio::test]
+    async fn test_perceptron_keeps_useful_predictions() {
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4);
+
+        for i in 0..1000u64 {
+            prefetcher.access(i * 8).await;
+        }
+        let predictions = prefetcher.access(8000).await;
+        println!("Predictions after training: {:?}", predictions);
+        assert!(predictions.contains(&8008), "Useful strided candidates should still be issued");
+    }
+
+    #[tokio::test]
+    async fn test_sync_access_matches_async() {
+        let mut sync: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4);
+        let mut asynchronous: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4);
+        let mut rx = sync.start_async_predictor().await;
+
+        for i in 0..20u64 {
+            let address = (i % 5) * 64 + i / 5;
+            assert_eq!(sync.access_sync(address), asynchronous.access(address).await);
+        }
+        assert_eq!(sync.observe(7, 100), asynchronous.access_with_context(7, 100).await);
+        assert_eq!(sync.get_stats(), asynchronous.get_stats());
+
+        // Batches of the sync path are still delivered to the channel
+        let batch = rx.try_recv().expect("observe should send a prediction batch");
+        assert_eq!(batch.address, 0);
+    }
+
+    #[tokio::test]
+    async fn test_delivery_policies() {
+        async fn delivered(policy: DeliveryPolicy, accesses: &[(u64, u64)]) -> (Vec<u64>, u64) {
+            let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4).with_delivery(policy, 2);
+            let mut rx = prefetcher.start_async_predictor().await;
+            for &(stream_id, address) in accesses {
+                prefetcher.observe(stream_id, address);
+            }
+            let mut addresses = Vec::new();
+            while let Some(batch) = rx.try_recv() {
+                addresses.push(batch.address);
+            }
+            (addresses, prefetcher.dropped_batches())
+        }
+
+        let single: Vec<(u64, u64)> = (0..5).map(|i| (1, i)).collect();
+        assert_eq!(delivered(DeliveryPolicy::DropNewest, &single).await, (vec![0, 1], 3));
+        assert_eq!(delivered(DeliveryPolicy::DropOldest, &single).await, (vec![3, 4], 3));
+        // observe never waits, so a full blocking channel drops the new batch
+        assert_eq!(delivered(DeliveryPolicy::Block, &single).await, (vec![0, 1], 3));
+
+        // Newer batches replace queued batches of their own stream
+        let interleaved = [(1, 10), (2, 20), (1, 11), (2, 21)];
+        assert_eq!(delivered(DeliveryPolicy::Coalesce, &interleaved).await, (vec![11, 21], 2));
+
+        // Blocking access waits for the consumer instead of dropping
+        let mut prefetcher: PredictivePrefetcher<u64> =
+            PredictivePrefetcher::new(4).with_delivery(DeliveryPolicy::Block, 1);
+        let mut rx = prefetcher.start_async_predictor().await;
+        let producer = tokio::spawn(async move {
+            for i in 0..5u64 {
+                prefetcher.access(i).await;
+            }
+            prefetcher
+        });
+        let mut received = Vec::new();
+        for _ in 0..5 {
+            received.push(rx.recv().await.unwrap().address);
+        }
+        let prefetcher = producer.await.unwrap();
+        assert_eq!(received, vec![0, 1, 2, 3, 4]);
+        assert_eq!(prefetcher.dropped_batches(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_multiple_subscribers() {
+        use futures::StreamExt;
+
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4);
+        let mut first = prefetcher.start_async_predictor().await;
+        let mut second = prefetcher.start_async_predictor().await;
+        let stream = prefetcher.subscribe();
+        let dropped = prefetcher.subscribe();
+        drop(dropped);
+        assert_eq!(prefetcher.subscriber_count(), 3);
+
+        for i in 0..4u64 {
+            prefetcher.access(i).await;
+        }
+        drop(prefetcher);
+
+        // Every subscriber sees every batch, including the one returned first
+        for rx in [&mut first, &mut second] {
+            let mut addresses = Vec::new();
+            while let Some(batch) = rx.recv().await {
+                addresses.push(batch.address);
+            }
+            assert_eq!(addresses, vec![0, 1, 2, 3]);
+        }
+        let addresses: Vec<u64> = stream.map(|batch| batch.address).collect().await;
+        assert_eq!(addresses, vec![0, 1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_interleaved_streams() {
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4);
+        let mut a_hit = false;
+        let mut b_hit = false;
+
+        println!("\nInterleaved stream test:");
+        for i in 0..16u64 {
+            let a = i * 8;
+            let b = 100_000 + i * 4;
+            let a_preds = prefetcher.access_with_context(1, a).await;
+            let b_preds = prefetcher.access_with_context(2, b).await;
+            println!("A: {} -> {:?}, B: {} -> {:?}", a, a_preds, b, b_preds);
+            if i >= 4 {
+                a_hit |= a_preds.contains(&(a + 8));
+                b_hit |= b_preds.contains(&(b + 4));
+                assert!(a_preds.iter().all(|&p| p < 100_000), "Stream A must not predict stream B addresses");
+                assert!(b_preds.iter().all(|&p| p >= 100_000), "Stream B must not predict stream A addresses");
+            }
+        }
+        assert!(a_hit && b_hit, "Each stream should keep its own stride");
+        assert_eq!(prefetcher.stream_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stream_table_is_bounded() {
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4).with_max_streams(3);
+
+        for stream in 0..10u64 {
+            for i in 0..4 {
+                prefetcher.access_with_context(stream, stream * 1000 + i).await;
+            }
+        }
+        assert_eq!(prefetcher.stream_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_pattern_table_is_bounded() {
+        for policy in [EvictionPolicy::Lru, EvictionPolicy::Lfu, EvictionPolicy::LowestConfidence] {
+            let mut prefetcher: PredictivePrefetcher<u64> =
+                PredictivePrefetcher::new(4).with_table_capacity(16, policy);
+
+            for i in 0..1000u64 {
+                prefetcher.access(i * 7).await;
+                prefetcher.access(3).await;
+            }
+
+            println!("{:?}: {} patterns, {} evictions", policy,
+                prefetcher.pattern_count(), prefetcher.pattern_evictions());
+            assert_eq!(prefetcher.pattern_count(), 16);
+            if policy == EvictionPolicy::LowestConfidence {
+                // The hot address keeps missing, so it may be evicted and re-learned
+                assert!(prefetcher.pattern_evictions() >= 1001 - 16);
+            } else {
+                assert_eq!(prefetcher.pattern_evictions(), 1001 - 16, "Hot address should stay resident");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delta_sequence_pattern() {
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::with_config(8, 0.2, 6);
+        let deltas = [1u64, 1, 6];
+        let mut addr = 0u64;
+        let mut replayed = false;
+
+        println!("\nDelta sequence test:");
+        for step in 0..30 {
+            let predictions = prefetcher.access(addr).await;
+            println!("Access: {}, Predictions: {:?}", addr, predictions);
+
+            // Expected addresses when replaying the +1, +1, +6 loop forward
+            let mut expected = Vec::new();
+            let mut next = addr;
+            for i in 0..predictions.len() {
+                next += deltas[(step + i) % 3];
+                expected.push(next);
+            }
+            if step >= 8 {
+                assert!(!predictions.is_empty(), "Should predict once the delta sequence is learned");
+                assert_eq!(predictions, expected, "Predictions should replay the delta sequence");
+                replayed |= predictions.len() >= 2;
+            }
+            addr += deltas[step % 3];
+        }
+        assert!(replayed, "Should produce multi-step predictions");
+    }
+
+    #[test]
+    fn test_markov_predictor_probabilities() {
+        let mut markov: MarkovPredictor<u64> = MarkovPredictor::new(1, MarkovMode::Address);
+        for &addr in &[10, 20, 10, 30, 10, 20, 10, 20, 10] {
+            markov.observe(addr);
+        }
+        let predictions = markov.predict(2);
+        println!("Successors of 10: {:?}", predictions);
+        assert_eq!(predictions.len(), 2);
+        assert_eq!(predictions[0].0, 20);
+        assert!((predictions[0].1 - 0.75).abs() < 1e-9);
+        assert_eq!(predictions[1].0, 30);
+        assert!((predictions[1].1 - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_markov_delta_mode_and_order() {
+        let mut markov: MarkovPredictor<u64> = MarkovPredictor::new(2, MarkovMode::Delta);
+        // The same +3, +5, -2 walk at two different base addresses
+        for base in [1000u64, 5000] {
+            let mut addr = base;
+            for &delta in [3i64, 5, -2].iter().cycle().take(9) {
+                markov.observe(addr);
+                addr = addr.checked_offset(delta).unwrap();
+            }
+        }
+        let predictions = markov.predict(1);
+        println!("Delta-mode prediction: {:?}", predictions);
+        assert_eq!(predictions.len(), 1);
+        assert_eq!(predictions[0].0, 5018, "Context +3, +5 should be followed by -2");
+        assert!(predictions[0].1 > 0.5);
+    }
+
+    #[tokio::test]
+    async fn test_markov_pattern_for_irregular_sequence() {
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(8);
+        let probes = [907u64, 13, 5521, 340, 72, 8810, 1999, 46, 3031, 610];
+        let mut correct = 0;
+
+        println!("\nMarkov pattern test:");
+        for round in 0..4 {
+            for (i, &addr) in probes.iter().enumerate() {
+                let predictions = prefetcher.access(addr).await;
+                let next = probes[(i + 1) % probes.len()];
+                if round == 3 {
+                    println!("Access: {}, Predictions: {:?}", addr, predictions);
+                    if predictions.contains(&next) {
+                        correct += 1;
+                    }
+                }
+            }
+        }
+        assert!(correct >= probes.len() / 2, "Markov chain should learn the probe sequence, got {}", correct);
+    }
+
+    // Predicts the access three steps ahead of a stride-10 walk
+    struct LookaheadPredictor;
+
+    impl Predictor<u64> for LookaheadPredictor {
+        fn pattern_type(&self) -> PatternType {
+            PatternType::Custom("lookahead".to_string())
+        }
+
+        fn predict(&mut self, access: &AccessContext<u64>, _max_candidates: usize) -> Vec<Candidate<u64>> {
+            vec![Candidate { address: access.address + 30, confidence: 1.0 }]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delayed_prefetch_counts_as_hit() {
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4)
+            .with_predictors(vec![Box::new(LookaheadPredictor)]);
+
+        for i in 0..10u64 {
+            prefetcher.access(i * 10).await;
+        }
+        let (hits, misses, _) = prefetcher.get_stats();
+        println!("Hits: {}, Misses: {}", hits, misses);
+        // Predictors need two accesses of history, so from the fifth access on
+        // every access was prefetched three accesses earlier
+        assert_eq!(hits, 6);
+        assert_eq!(misses, 4);
+        // The last three lookaheads; the first access's fallback candidate
+        // has no confidence and is not issued
+        assert_eq!(prefetcher.outstanding_count(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_outstanding_lifetime() {
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4)
+            .with_predictors(vec![Box::new(LookaheadPredictor)])
+            .with_outstanding(16, 2);
+
+        for i in 0..10u64 {
+            prefetcher.access(i * 10).await;
+        }
+        let (hits, _, _) = prefetcher.get_stats();
+        let stats = prefetcher.stats();
+        println!("Hits: {}, Stats: {:?}", hits, stats);
+        assert_eq!(hits, 0, "Prefetches should expire before they are used");
+        assert_eq!(stats.useless_prefetches, 6);
+    }
+
+    // Prefetches an address just past each access, which is never used
+    struct NeverUsedPredictor {
+        feedback: Arc<Mutex<Vec<(u64, bool)>>>,
+    }
+
+    impl Predictor<u64> for NeverUsedPredictor {
+        fn pattern_type(&self) -> PatternType {
+            PatternType::Custom("never-used".to_string())
+        }
+
+        fn predict(&mut self, access: &AccessContext<u64>, _max_candidates: usize) -> Vec<Candidate<u64>> {
+            vec![Candidate { address: access.address + 5, confidence: 0.9 }]
+        }
+
+        fn feedback(&mut self, _stream_id: u64, address: u64, hit: bool) {
+            self.feedback.lock().unwrap().push((address, hit));
+        }
+    }
+
+    #[test]
+    fn test_feedback_reports_prefetched_address() {
+        // Expired by a short lifetime, then displaced by a small buffer; the
+        // last `outstanding` prefetches are still in the buffer at the end
+        for (capacity, lifetime, outstanding) in [(16, 2, 3), (2, 100, 2)] {
+            let feedback = Arc::new(Mutex::new(Vec::new()));
+            let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4)
+                .with_predictors(vec![Box::new(NeverUsedPredictor { feedback: feedback.clone() })])
+                .with_outstanding(capacity, lifetime);
+            for i in 0..10u64 {
+                prefetcher.access_sync(i * 10);
+            }
+
+            let feedback = feedback.lock().unwrap();
+            println!("Feedback: {:?}", feedback);
+            // Predictors need two accesses of history, so the second access
+            // is the first to prefetch access + 5
+            let expected: Vec<(u64, bool)> = (1..10 - outstanding).map(|i| (i * 10 + 5, false)).collect();
+            assert_eq!(*feedback, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_predictor() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4)
+            .with_predictors(vec![Box::new(NextPagePredictor { hits: hits.clone() })]);
+        let mut rx = prefetcher.start_async_predictor().await;
+
+        for page in 0..8u64 {
+            let addr = page * 4096 + 128;
+            let predictions = prefetcher.access(addr).await;
+            println!("Access: {}, Predictions: {:?}", addr, predictions);
+            if page >= 1 {
+                assert!(predictions.contains(&(addr + 4096)), "Custom predictor candidates should be issued");
+            }
+        }
+
+        drop(prefetcher);
+        let mut custom_batches = 0;
+        while let Some(batch) = rx.recv().await {
+            if batch.pattern_type == PatternType::Custom("next-page".to_string()) {
+                custom_batches += 1;
+            }
+        }
+        assert!(custom_batches >= 6, "Batches should report the custom pattern type");
+        assert!(hits.load(Ordering::Relaxed) >= 5, "Predictor should receive hit feedback");
+    }
+
+    #[tokio::test]
+    async fn test_predictor_list_order() {
+        // Without the strided detector a stride of 3 is never predicted as such
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4)
+            .with_predictors(vec![Box::new(SequentialPredictor::default())]);
+
+        for i in 0..10u64 {
+            let predictions = prefetcher.access(i * 3).await;
+            assert!(!predictions.contains(&(i * 3 + 3)), "Stride 3 should not be detected");
+        }
+    }
+
+    async fn count_correct(mode: SelectionMode) -> usize {
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4).with_selection(mode);
+        let probes = [907u64, 13, 5521, 340, 72, 8810, 1999, 46, 3031, 610];
+        let mut correct = 0;
+
+        for round in 0..6 {
+            for (i, &addr) in probes.iter().enumerate() {
+                let predictions = prefetcher.access(addr).await;
+                if round >= 3 && predictions.contains(&probes[(i + 1) % probes.len()]) {
+                    correct += 1;
+                }
+            }
+        }
+        println!("{:?}: {:?}", mode, prefetcher.predictor_accuracy(0));
+        correct
+    }
+
+    #[tokio::test]
+    async fn test_tournament_selection() {
+        let first_match = count_correct(SelectionMode::FirstMatch).await;
+        let tournament = count_correct(SelectionMode::Tournament).await;
+        let weighted = count_correct(SelectionMode::Weighted).await;
+        println!("Correct - first match: {}, tournament: {}, weighted: {}", first_match, tournament, weighted);
+        assert!(tournament > first_match, "Tournament should prefer the accurate Markov predictor");
+        assert!(weighted > first_match, "Weighted selection should prefer the accurate Markov predictor");
+    }
+
+    #[tokio::test]
+    async fn test_predictor_accuracy_tracking() {
+        let mut prefetcher: PredictivePrefetcher<u64> =
+            PredictivePrefetcher::new(4).with_selection(SelectionMode::Tournament);
+        for i in 0..50u64 {
+            prefetcher.access(i * 4).await;
+        }
+        let accuracy = prefetcher.predictor_accuracy(0);
+        println!("Accuracy: {:?}", accuracy);
+        let strided = accuracy.iter().find(|(t, _)| *t == PatternType::Strided).unwrap().1;
+        let sequential = accuracy.iter().find(|(t, _)| *t == PatternType::Sequential).unwrap().1;
+        assert!(strided > 0.9, "Strided predictor should be scored as accurate");
+        assert!(sequential < strided, "Sequential predictor should score below strided on a stride of 4");
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_stats() {
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4);
+
+        for i in 0..200u64 {
+            prefetcher.access(i * 16).await;
+        }
+        let stats = prefetcher.stats();
+        println!("Strided stats: {:?}", stats);
+        assert_eq!(stats.demand_accesses, 200);
+        assert!(stats.prefetches_issued > 0);
+        assert!(stats.accuracy() > 0.9, "Strided prefetches should almost always be used");
+        assert!(stats.coverage() > 0.9, "Strided accesses should almost always be covered");
+        assert!(stats.useful_prefetches + stats.useless_prefetches <= stats.prefetches_issued);
+        let strided = stats.by_pattern[&PatternType::Strided];
+        assert!(strided.useful > 150, "Useful prefetches should be attributed to the strided pattern");
+        assert!(stats.late_prefetches > 0, "Prefetches consumed by the next access count as late");
+
+        prefetcher.reset_stats();
+        let stats = prefetcher.stats();
+        assert_eq!(stats, Default::default());
+        assert_eq!(prefetcher.get_stats(), (0, 0, 0.0));
+    }
+
+    #[tokio::test]
+    async fn test_useless_prefetches_and_timeliness() {
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4).with_min_lead(1);
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+
+        for _ in 0..500 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            prefetcher.access(state % 1_000_000).await;
+        }
+        let stats = prefetcher.stats();
+        println!("Random stats: {:?}", stats);
+        assert!(stats.useless_prefetches > 0, "Unused prefetches should expire as useless");
+        assert!(stats.accuracy() < 0.1);
+        assert!(stats.coverage() < 0.1);
+        assert_eq!(stats.late_prefetches, 0, "Nothing is late with a minimum lead of one access");
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let config = PrefetcherConfig::default()
+            .with_min_confidence(0.3)
+            .with_learning_rate(0.1)
+            .with_window_size(1, 8)
+            .with_window_thresholds(0.6, 0.4)
+            .with_detection_ratios(0.75, 0.75, 0.6);
+        let prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::from_config(config.clone()).unwrap();
+        assert_eq!(prefetcher.config(), &config);
+
+        let error = |config: PrefetcherConfig| PredictivePrefetcher::<u64>::from_config(config).err();
+        assert_eq!(error(PrefetcherConfig::default().with_history_size(0)), Some(ConfigError::EmptyHistory));
+        assert_eq!(
+            error(PrefetcherConfig::default().with_region_size(0)),
+            Some(ConfigError::RegionSize { size: 0, max: PrefetcherConfig::MAX_REGION_SIZE })
+        );
+        assert_eq!(
+            error(PrefetcherConfig::default().with_region_size(1 << 30)),
+            Some(ConfigError::RegionSize { size: 1 << 30, max: 4096 })
+        );
+        assert!(PredictivePrefetcher::<u64>::from_config(PrefetcherConfig::default().with_region_size(4096)).is_ok());
+        assert_eq!(SpatialPredictor::new(1 << 30).region_size(), PrefetcherConfig::MAX_REGION_SIZE);
+        assert_eq!(
+            error(PrefetcherConfig::default().with_window_size(4, 2)),
+            Some(ConfigError::WindowSize { min: 4, max: 2 })
+        );
+        assert_eq!(
+            error(PrefetcherConfig::default().with_min_confidence(1.5)),
+            Some(ConfigError::OutOfRange { field: "min_confidence", value: 1.5 })
+        );
+        assert!(matches!(
+            error(PrefetcherConfig::default().with_learning_rate(f64::NAN)),
+            Some(ConfigError::OutOfRange { field: "learning_rate", .. })
+        ));
+    }
+
+    #[test]
+    fn test_min_confidence_suppresses_mispredicting_patterns() {
+        // A small set of addresses revisited in random order: triggers recur
+        // but what follows them does not
+        let issued = |min_confidence: f64| {
+            let config = PrefetcherConfig::default().with_min_confidence(min_confidence);
+            let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::from_config(config).unwrap();
+            let mut state = 0x2545_f491_4f6c_dd1du64;
+            for _ in 0..2000 {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                prefetcher.access_sync(state % 64 * 1000);
+            }
+            prefetcher.stats().prefetches_issued
+        };
+
+        let lenient = issued(0.0);
+        let strict = issued(0.5);
+        println!("Prefetches issued: {} lenient, {} strict", lenient, strict);
+        assert!(strict * 2 < lenient, "Known triggers that mispredict should stop issuing prefetches");
+    }
+
+    #[test]
+    fn test_min_confidence_applies_to_every_trigger() {
+        // A stride of 3 broken by a jump every 8 accesses: every access is a
+        // new trigger, and the strided detector is never fully confident
+        let predicted = |min_confidence: f64| {
+            let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::with_config(8, min_confidence, 4);
+            (0..1000u64).map(|i| prefetcher.access_sync(i * 3 + i / 8 * 1000).len()).sum::<usize>()
+        };
+        let lenient = predicted(0.2);
+        let strict = predicted(0.99);
+        println!("Predictions: {} lenient, {} strict", lenient, strict);
+        assert!(lenient > 1000);
+        assert!(strict * 4 < lenient, "Patterns below min_confidence should not be issued");
+
+        // Without predictors every access falls back to an unconfident guess,
+        // which revisited triggers do not issue either
+        let fallback_issued = |min_confidence: f64| {
+            let config = PrefetcherConfig::default().with_min_confidence(min_confidence);
+            let mut prefetcher: PredictivePrefetcher<u64> =
+                PredictivePrefetcher::from_config(config).unwrap().with_predictors(Vec::new());
+            for i in 0..100u64 {
+                prefetcher.access_sync(i % 2 * 100);
+            }
+            prefetcher.stats().prefetches_issued
+        };
+        assert_eq!(fallback_issued(0.2), 0);
+        assert!(fallback_issued(0.0) > 0);
+
+        assert_eq!(
+            PredictivePrefetcher::<u64>::from_config(PrefetcherConfig::default().with_min_confidence(0.8)).err(),
+            Some(ConfigError::Confidence { initial: 0.5, min: 0.8 })
+        );
+    }
+
+    #[test]
+    fn test_prefetch_distance_adapts() {
+        // A fixed degree, so only the distance can make prefetches timely
+        let run = |max_distance: usize, lifetime: u64| {
+            let config = PrefetcherConfig::default().with_window_size(2, 2).with_distance(0, max_distance);
+            let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::from_config(config)
+                .unwrap()
+                .with_min_lead(4)
+                .with_outstanding(256, lifetime);
+            let mut last = Vec::new();
+            for i in 0..500u64 {
+                last = prefetcher.access_sync(i);
+            }
+            let distance = prefetcher.prefetch_distance(DEFAULT_STREAM, &PatternType::Sequential).unwrap();
+            (distance, last, prefetcher.stats())
+        };
+
+        let (distance, _, fixed) = run(0, 64);
+        assert_eq!(distance, 0, "Distance never exceeds its maximum");
+        let (distance, last, adaptive) = run(8, 64);
+        println!("Distance {}, last predictions {:?}", distance, last);
+        println!("Late prefetches: {} fixed, {} adaptive", fixed.late_prefetches, adaptive.late_prefetches);
+        assert!(distance >= 3, "Late prefetches should push the distance ahead");
+        assert!(last[0] > 500, "Predictions should skip ahead of the next access");
+        assert!(adaptive.late_prefetches * 4 < fixed.late_prefetches);
+        assert!(adaptive.accuracy() > 0.9);
+
+        // Prefetches that far ahead expire unused and pull the distance back
+        let (short, _, stats) = run(8, 2);
+        println!("Distance with a short lifetime: {}, {:?}", short, stats);
+        assert!(stats.useless_prefetches > 0);
+        assert!(short < distance, "Useless prefetches should bring the distance closer");
+    }
+
+    #[test]
+    fn test_prefetch_degree_adapts() {
+        // Every access is a new trigger, so the degree has to be learned for
+        // the stream's pattern rather than per trigger
+        let run = |lifetime: u64| {
+            let config = PrefetcherConfig::default().with_window_size(2, 8);
+            let mut prefetcher: PredictivePrefetcher<u64> =
+                PredictivePrefetcher::from_config(config).unwrap().with_outstanding(256, lifetime);
+            let mut last = Vec::new();
+            for i in 0..200u64 {
+                last = prefetcher.access_sync(i * 3);
+            }
+            (prefetcher.prefetch_degree(DEFAULT_STREAM, &PatternType::Strided).unwrap(), last)
+        };
+
+        let (degree, last) = run(64);
+        println!("Degree {}, last predictions {:?}", degree, last);
+        assert_eq!(degree, 8, "Useful prefetches should grow the degree to its maximum");
+        assert_eq!(last.len(), 8);
+
+        // Only the next access arrives before the prefetches expire
+        let (degree, _) = run(1);
+        assert_eq!(degree, 2, "Useless prefetches should shrink the degree");
+        assert_eq!(PredictivePrefetcher::<u64>::new(8).prefetch_degree(DEFAULT_STREAM, &PatternType::Strided), None);
+    }
+
+    #[test]
+    fn test_spatial_footprint_pattern() {
+        // Every page is entered at offset 0 and then touches the same lines,
+        // in a different order each time, so no stride or delta sequence fits
+        let footprint = [0u64, 5, 9, 17, 30, 41, 52, 60];
+        let page = |p: u64| {
+            let mut offsets = footprint[1..].to_vec();
+            offsets.rotate_left((p as usize * 3) % (footprint.len() - 1));
+            if p % 2 == 1 {
+                offsets.reverse();
+            }
+            std::iter::once(0).chain(offsets).map(move |offset| 0x10_0000 + p * 64 + offset)
+        };
+
+        // The predictor alone: once the first pages have left the active
+        // regions, a trigger predicts the rest of the footprint, nearest first
+        let mut spatial = SpatialPredictor::new(64);
+        let mut history = Vec::new();
+        let mut trigger_predictions = Vec::new();
+        for p in 0..48 {
+            for address in page(p) {
+                history.push(address);
+                let access = AccessContext { stream_id: 1, address, history: &history[history.len().saturating_sub(8)..] };
+                Predictor::<u64>::observe(&mut spatial, &access);
+                let candidates = spatial.predict(&access, 16);
+                if address % 64 == 0 {
+                    trigger_predictions = candidates.iter().map(|c| c.address - address).collect();
+                }
+            }
+        }
+        println!("Predicted footprint: {:?}", trigger_predictions);
+        assert_eq!(trigger_predictions, footprint[1..].to_vec());
+
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(8);
+        for p in 0..200 {
+            for address in page(p) {
+                prefetcher.observe(1, address);
+            }
+        }
+        let stats = prefetcher.stats();
+        let spatial = stats.by_pattern[&PatternType::Spatial];
+        println!("Spatial prefetches: {:?}, coverage {:.2}", spatial, stats.coverage());
+        assert!(spatial.useful > 600, "Most of the footprint should be prefetched");
+        assert!(spatial.useful as f64 / spatial.issued as f64 > 0.9);
+    }
+
+    #[test]
+    fn test_best_offset_predictor() {
+        let learn = |lead: usize, addresses: &mut dyn Iterator<Item = u64>| {
+            let mut predictor = BestOffsetPredictor::new(lead);
+            for address in addresses {
+                let access = AccessContext { stream_id: DEFAULT_STREAM, address, history: &[address] };
+                Predictor::<u64>::observe(&mut predictor, &access);
+            }
+            predictor
+        };
+
+        // The smallest offset that covers a stride of 5 wins, unless it
+        // leaves less than `lead` accesses for the prefetch to arrive
+        let predictor = learn(0, &mut (0..3000u64).map(|i| 1_000_000 + i * 5));
+        assert_eq!(predictor.best_offset(), Some(5));
+        let predictor = learn(4, &mut (0..3000u64).map(|i| 1_000_000 + i * 5));
+        assert_eq!(predictor.best_offset(), Some(25));
+
+        // Random accesses turn prefetching off
+        let mut state = 12345u64;
+        let mut random = std::iter::repeat_with(move || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            state >> 20
+        });
+        let mut predictor = learn(0, &mut random.by_ref().take(12000));
+        assert_eq!(predictor.best_offset(), None);
+        let access = AccessContext { stream_id: DEFAULT_STREAM, address: 7u64, history: &[7] };
+        assert!(predictor.predict(&access, 4).is_empty());
+
+        // As the only predictor it covers two arrays walked in lockstep, which
+        // a single stream sees as alternating deltas
+        let mut prefetcher: PredictivePrefetcher<u64> =
+            PredictivePrefetcher::new(8).with_predictors(vec![Box::new(BestOffsetPredictor::default())]);
+        for i in 0..4000u64 {
+            prefetcher.access_sync(0x10_0000 + i * 2);
+            prefetcher.access_sync(0x90_0000 + i * 2);
+        }
+        let stats = prefetcher.stats();
+        let best_offset = stats.by_pattern[&PatternType::BestOffset];
+        println!("Best-offset prefetches: {:?}, coverage {:.2}", best_offset, stats.coverage());
+        assert!(stats.coverage() > 0.75);
+        assert!(best_offset.useful as f64 / best_offset.issued as f64 > 0.9);
+    }
+}
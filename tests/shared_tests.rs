@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod tests {
+    use ml_prefetcher::{PatternType, PrefetcherConfig, PredictivePrefetcher, SharedPrefetcher};
+    use std::sync::Arc;
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_shared_prefetcher_concurrent_streams() {
+        assert_send_sync::<SharedPrefetcher<u64>>();
+        let prefetcher: SharedPrefetcher<u64> = SharedPrefetcher::from_config(PrefetcherConfig::default(), 4).unwrap();
+        assert_eq!(prefetcher.shard_count(), 4);
+
+        // Each thread scans its own streams with its own stride
+        thread::scope(|scope| {
+            for worker in 0..8u64 {
+                let prefetcher = &prefetcher;
+                scope.spawn(move || {
+                    let stride = worker + 1;
+                    let base = worker << 32;
+                    let mut correct = 0;
+                    for i in 0..200 {
+                        let predictions = prefetcher.access(worker, base + i * stride);
+                        if predictions.first() == Some(&(base + (i + 1) * stride)) {
+                            correct += 1;
+                        }
+                    }
+                    assert!(correct >= 190, "worker {} predicted {} of 200", worker, correct);
+                });
+            }
+        });
+
+        let stats = prefetcher.stats();
+        println!("Shared stats: {:?}", stats);
+        assert_eq!(stats.demand_accesses, 8 * 200);
+        assert_eq!(prefetcher.stream_count(), 8);
+        assert!(stats.accuracy() > 0.9);
+        let (hits, misses, _) = prefetcher.get_stats();
+        assert!(hits > 0 && hits + misses > 0);
+
+        prefetcher.reset_stats();
+        assert_eq!(prefetcher.stats().demand_accesses, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_shared_prefetcher_across_tasks() {
+        let prefetcher = Arc::new(SharedPrefetcher::new(2, || {
+            PredictivePrefetcher::<u64>::new(8).with_max_streams(4)
+        }));
+
+        let tasks: Vec<_> = (0..4u64)
+            .map(|stream| {
+                let prefetcher = Arc::clone(&prefetcher);
+                tokio::spawn(async move {
+                    for i in 0..50 {
+                        prefetcher.access(stream, stream * 10_000 + i * 64);
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert_eq!(prefetcher.stats().demand_accesses, 200);
+        for stream in 0..4 {
+            let distance = prefetcher.with_shard(stream, |shard| shard.prefetch_distance(stream, &PatternType::Strided));
+            assert_eq!(distance, Some(0));
+        }
+    }
+
+    #[test]
+    fn test_access_batch_matches_single_accesses() {
+        let accesses: Vec<(u64, u64)> = (0..300u64).map(|i| (i % 6, (i % 6) * 1_000_000 + (i / 6) * (i % 6 + 1))).collect();
+
+        let batched: SharedPrefetcher<u64> = SharedPrefetcher::from_config(PrefetcherConfig::default(), 3).unwrap();
+        let mut results = Vec::new();
+        for chunk in accesses.chunks(64) {
+            results.extend(batched.access_batch(chunk));
+        }
+
+        let single: SharedPrefetcher<u64> = SharedPrefetcher::from_config(PrefetcherConfig::default(), 3).unwrap();
+        let expected: Vec<Vec<u64>> = accesses.iter().map(|&(stream, address)| single.access(stream, address)).collect();
+        assert_eq!(results, expected);
+        assert_eq!(batched.stats(), single.stats());
+        assert!(batched.access_batch(&[]).is_empty());
+
+        // The plain prefetcher processes a batch in order on the default stream
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4);
+        let predictions = prefetcher.access_batch(&[10, 11, 12, 13]);
+        assert_eq!(predictions.len(), 4);
+        assert_eq!(predictions[3].first(), Some(&14));
+    }
+}
@@ -0,0 +1,130 @@
+#[cfg(test)]
+mod tests {
+    use ml_prefetcher::{
+        MarkovMode, PredictivePrefetcher, PrefetcherConfig, SequentialPredictor, SnapshotError, SnapshotFormat,
+        SNAPSHOT_VERSION,
+    };
+
+    const PROBES: [u64; 10] = [907, 13, 5521, 340, 72, 8810, 1999, 46, 3031, 610];
+
+    // Learns a sequential scan in one stream and an irregular walk in another
+    fn trained() -> PredictivePrefetcher<u64> {
+        let config = PrefetcherConfig::default().with_distance(0, 4);
+        let mut prefetcher = PredictivePrefetcher::from_config(config).unwrap();
+        for round in 0..4u64 {
+            for i in 0..16 {
+                prefetcher.observe(1, 1000 * round + i);
+            }
+            for &addr in PROBES.iter() {
+                prefetcher.observe(2, addr);
+            }
+        }
+        prefetcher
+    }
+
+    fn round_trip(format: SnapshotFormat) {
+        let mut original = trained();
+        let mut buffer = Vec::new();
+        original.save(&mut buffer, format).unwrap();
+
+        let mut restored: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4);
+        restored.load(buffer.as_slice(), format).unwrap();
+        assert_eq!(restored.config(), original.config());
+        assert_eq!(restored.stats(), original.stats());
+        assert_eq!(restored.get_stats(), original.get_stats());
+        assert_eq!(restored.pattern_count(), original.pattern_count());
+        assert_eq!(restored.stream_count(), original.stream_count());
+        assert_eq!(restored.outstanding_count(), original.outstanding_count());
+
+        // The restored prefetcher continues both streams like the original
+        for addr in [5000u64, 5001, 5002] {
+            assert_eq!(restored.observe(1, addr), original.observe(1, addr));
+        }
+        for &addr in PROBES.iter().take(3) {
+            assert_eq!(restored.observe(2, addr), original.observe(2, addr));
+        }
+    }
+
+    #[test]
+    fn test_binary_and_json_round_trip() {
+        round_trip(SnapshotFormat::Binary);
+        round_trip(SnapshotFormat::Json);
+    }
+
+    #[test]
+    fn test_incompatible_snapshots_are_rejected() {
+        let prefetcher = trained();
+        let mut binary = Vec::new();
+        prefetcher.save(&mut binary, SnapshotFormat::Binary).unwrap();
+        let mut json = Vec::new();
+        prefetcher.save(&mut json, SnapshotFormat::Json).unwrap();
+
+        // Version check in both encodings
+        let mut newer = binary.clone();
+        newer[4..8].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        let mut target: PredictivePrefetcher<u64> = PredictivePrefetcher::new(4);
+        let err = target.load(newer.as_slice(), SnapshotFormat::Binary).unwrap_err();
+        println!("{}", err);
+        assert!(matches!(err, SnapshotError::Version { found, supported } if found == SNAPSHOT_VERSION + 1 && supported == SNAPSHOT_VERSION));
+
+        let mut value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        value["version"] = serde_json::json!(SNAPSHOT_VERSION + 1);
+        let newer = serde_json::to_vec(&value).unwrap();
+        let err = target.load(newer.as_slice(), SnapshotFormat::Json).unwrap_err();
+        assert!(matches!(err, SnapshotError::Version { .. }));
+
+        // Not a snapshot, or the wrong encoding
+        let err = target.load(&b"not a snapshot"[..], SnapshotFormat::Binary).unwrap_err();
+        assert!(matches!(err, SnapshotError::Encoding(_)));
+        let err = target.load(binary.as_slice(), SnapshotFormat::Json).unwrap_err();
+        assert!(matches!(err, SnapshotError::Encoding(_)));
+
+        // Another address type, told apart by width and signedness
+        assert_eq!(value["state"]["address_type"], serde_json::json!({ "bits": 64, "signed": false }));
+        let mut narrow: PredictivePrefetcher<u32> = PredictivePrefetcher::new(4);
+        let err = narrow.load(binary.as_slice(), SnapshotFormat::Binary).unwrap_err();
+        println!("{}", err);
+        assert!(matches!(err, SnapshotError::Incompatible(_)));
+        let mut signed: PredictivePrefetcher<i64> = PredictivePrefetcher::new(4);
+        let err = signed.load(binary.as_slice(), SnapshotFormat::Binary).unwrap_err();
+        assert_eq!(err.to_string(), "incompatible snapshot: snapshot holds u64 addresses, prefetcher uses i64");
+
+        // Other predictors, or a Markov chain of another order
+        let mut sequential_only: PredictivePrefetcher<u64> =
+            PredictivePrefetcher::new(4).with_predictors(vec![Box::new(SequentialPredictor::default())]);
+        let err = sequential_only.load(binary.as_slice(), SnapshotFormat::Binary).unwrap_err();
+        println!("{}", err);
+        assert!(matches!(err, SnapshotError::Incompatible(_)));
+
+        let mut second_order: PredictivePrefetcher<u64> =
+            PredictivePrefetcher::new(4).with_markov(2, MarkovMode::Address);
+        second_order.observe(7, 1);
+        let err = second_order.load(binary.as_slice(), SnapshotFormat::Binary).unwrap_err();
+        assert!(matches!(err, SnapshotError::Incompatible(_)));
+        // A failed load leaves the prefetcher untouched
+        assert_eq!(second_order.stream_count(), 1);
+        assert_eq!(second_order.config(), &PrefetcherConfig::default().with_history_size(4));
+    }
+
+    #[test]
+    fn test_detection_ratios_must_match() {
+        let config = PrefetcherConfig::default().with_detection_ratios(1.0, 1.0, 1.0);
+        let mut original: PredictivePrefetcher<u64> = PredictivePrefetcher::from_config(config.clone()).unwrap();
+        let mut buffer = Vec::new();
+        original.save(&mut buffer, SnapshotFormat::Binary).unwrap();
+
+        // Default predictors would detect patterns the strict ones do not
+        let mut lenient: PredictivePrefetcher<u64> = PredictivePrefetcher::new(8);
+        let err = lenient.load(buffer.as_slice(), SnapshotFormat::Binary).unwrap_err();
+        println!("{}", err);
+        assert!(matches!(err, SnapshotError::Incompatible(_)));
+        assert_eq!(lenient.config(), &PrefetcherConfig::default());
+
+        let mut strict: PredictivePrefetcher<u64> = PredictivePrefetcher::from_config(config.clone()).unwrap();
+        strict.load(buffer.as_slice(), SnapshotFormat::Binary).unwrap();
+        assert_eq!(strict.config(), &config);
+        for addr in [0u64, 1, 2, 3, 10, 11, 12, 13] {
+            assert_eq!(strict.access_sync(addr), original.access_sync(addr));
+        }
+    }
+}
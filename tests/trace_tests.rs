@@ -0,0 +1,199 @@
+#[cfg(test)]
+mod tests {
+    use ml_prefetcher::trace::{
+        analyze, replay, replay_with, write_binary, AccessKind, Partition, ReplayOptions, TraceError,
+        TraceFormat, TraceReader, TraceRecord,
+    };
+    use ml_prefetcher::{PatternType, PredictivePrefetcher};
+    use std::io::Cursor;
+
+    fn read(format: TraceFormat, data: &[u8]) -> Result<Vec<TraceRecord>, TraceError> {
+        TraceReader::new(Cursor::new(data), format).collect()
+    }
+
+    #[test]
+    fn test_text_trace() {
+        let records = read(TraceFormat::Text, b"# sequential walk\n0x1000\n\n4097\n  0X1002  \n").unwrap();
+        let addresses: Vec<u64> = records.iter().map(|r| r.address).collect();
+        assert_eq!(addresses, vec![0x1000, 0x1001, 0x1002]);
+        assert_eq!(records[2].timestamp, 2);
+        assert_eq!(records[2].kind, AccessKind::Read);
+
+        match read(TraceFormat::Text, b"16\nzz\n") {
+            Err(TraceError::Parse { record, .. }) => assert_eq!(record, 2),
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_csv_trace() {
+        let data = b"timestamp,pc,address,rw\n10, 0x400a, 0x1000, r\n11,0x400b,4160,W\n";
+        let records = read(TraceFormat::Csv, data).unwrap();
+        assert_eq!(records, vec![
+            TraceRecord { timestamp: 10, pc: 0x400a, address: 0x1000, kind: AccessKind::Read },
+            TraceRecord { timestamp: 11, pc: 0x400b, address: 4160, kind: AccessKind::Write },
+        ]);
+
+        assert!(read(TraceFormat::Csv, b"1,2,3\n").is_err(), "Missing columns must be rejected");
+        assert!(read(TraceFormat::Csv, b"1,2,3,x\n").is_err(), "Unknown access kinds must be rejected");
+        assert_eq!(TraceFormat::from_path("run.CSV"), TraceFormat::Csv);
+        assert_eq!(TraceFormat::from_path("run.bin"), TraceFormat::Binary);
+        assert_eq!(TraceFormat::from_path("run.trace"), TraceFormat::Text);
+    }
+
+    #[test]
+    fn test_binary_trace_roundtrip() {
+        let records: Vec<TraceRecord> = (0..100u64)
+            .map(|i| TraceRecord {
+                timestamp: i,
+                pc: 0x400000 + i % 3,
+                address: 0xffff_0000_0000 + i * 64,
+                kind: if i % 4 == 0 { AccessKind::Write } else { AccessKind::Read },
+            })
+            .collect();
+        let mut data = Vec::new();
+        write_binary(&mut data, &records).unwrap();
+        assert_eq!(data.len(), 5 + records.len() * 25);
+        assert_eq!(read(TraceFormat::Binary, &data).unwrap(), records);
+
+        // A cut-off record and a foreign file are both errors
+        assert!(read(TraceFormat::Binary, &data[..data.len() - 3]).is_err());
+        assert!(read(TraceFormat::Binary, b"0x1000\n0x1001\n").is_err());
+    }
+
+    #[test]
+    fn test_replay() {
+        let text: String = (0..200u64).map(|i| format!("{:#x}\n", 0x10_0000 + i * 8)).collect();
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(8);
+        let report = replay(&mut prefetcher, TraceReader::new(Cursor::new(text), TraceFormat::Text)).unwrap();
+        println!("Replay report: {:?}", report);
+
+        assert_eq!(report.records, 200);
+        assert_eq!(report.reads, 200);
+        assert_eq!(report.stats.demand_accesses, 200);
+        assert_eq!(u64::from(report.hits + report.misses), report.records);
+        assert!(report.predictions > 0);
+        assert!(report.accuracy > 0.9, "A strided trace should be almost fully covered");
+
+        // Replay stops at the first bad record
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(8);
+        let result = replay(&mut prefetcher, TraceReader::new(Cursor::new("1\n2\nbad\n3\n"), TraceFormat::Text));
+        assert!(matches!(result, Err(TraceError::Parse { record: 3, .. })));
+        assert_eq!(prefetcher.stats().demand_accesses, 2);
+    }
+
+    // A ChampSim `input_instr` with the given memory operands
+    fn champsim_instr(ip: u64, sources: &[u64], destinations: &[u64]) -> Vec<u8> {
+        let mut record = vec![0u8; 64];
+        record[..8].copy_from_slice(&ip.to_le_bytes());
+        for (i, address) in destinations.iter().enumerate() {
+            record[16 + i * 8..24 + i * 8].copy_from_slice(&address.to_le_bytes());
+        }
+        for (i, address) in sources.iter().enumerate() {
+            record[32 + i * 8..40 + i * 8].copy_from_slice(&address.to_le_bytes());
+        }
+        record
+    }
+
+    #[test]
+    fn test_champsim_trace() {
+        let mut data = champsim_instr(0x401000, &[0x7000, 0x7040], &[0x9000]);
+        data.extend(champsim_instr(0x401004, &[], &[]));
+        data.extend(champsim_instr(0x401008, &[0x7080], &[]));
+
+        let records = read(TraceFormat::ChampSim, &data).unwrap();
+        assert_eq!(records, vec![
+            TraceRecord { timestamp: 0, pc: 0x401000, address: 0x7000, kind: AccessKind::Read },
+            TraceRecord { timestamp: 0, pc: 0x401000, address: 0x7040, kind: AccessKind::Read },
+            TraceRecord { timestamp: 0, pc: 0x401000, address: 0x9000, kind: AccessKind::Write },
+            TraceRecord { timestamp: 2, pc: 0x401008, address: 0x7080, kind: AccessKind::Read },
+        ]);
+        assert!(read(TraceFormat::ChampSim, &data[..100]).is_err(), "A cut-off instruction must be rejected");
+        assert_eq!(TraceFormat::from_path("605.mcf_s.champsimtrace"), TraceFormat::ChampSim);
+    }
+
+    #[test]
+    fn test_lackey_trace() {
+        let data = b"==1234== Lackey, an example Valgrind tool\n\
+            I  04000000,3\n\
+             L 04222cac,8\n\
+            I  04000003,5\n\
+             S 7ff000398,8\n\
+             M 0421e8b8,4\n\
+            ==1234== Exit\n";
+        let records = read(TraceFormat::Lackey, data).unwrap();
+        assert_eq!(records, vec![
+            TraceRecord { timestamp: 0, pc: 0x4000000, address: 0x4222cac, kind: AccessKind::Read },
+            TraceRecord { timestamp: 1, pc: 0x4000003, address: 0x7ff000398, kind: AccessKind::Write },
+            TraceRecord { timestamp: 1, pc: 0x4000003, address: 0x421e8b8, kind: AccessKind::Write },
+        ]);
+        assert!(read(TraceFormat::Lackey, b" X 1000,4\n").is_err());
+    }
+
+    #[test]
+    fn test_replay_lines_per_pc() {
+        // Two loads per instruction walking separate arrays 8 bytes at a time
+        let mut data = Vec::new();
+        for i in 0..256u64 {
+            data.extend(champsim_instr(0x401000, &[0x10_0000 + i * 8], &[]));
+            data.extend(champsim_instr(0x401010, &[0x80_0000 + i * 8], &[]));
+        }
+
+        let mut prefetcher: PredictivePrefetcher<u64> = PredictivePrefetcher::new(8);
+        let reader = TraceReader::new(Cursor::new(&data), TraceFormat::ChampSim);
+        let report = replay_with(&mut prefetcher, reader, ReplayOptions::lines(64)).unwrap();
+        println!("Replay report: {:?}", report);
+        assert_eq!(report.records, 512);
+        assert_eq!(prefetcher.stream_count(), 2, "Each instruction pointer should get its own stream");
+
+        // Byte addresses become 64 byte line numbers
+        let options = ReplayOptions::lines(64);
+        let record = TraceRecord { address: 0x10_0038, pc: 0x401000, ..TraceRecord::default() };
+        assert_eq!(options.address(&record), 0x4000);
+        assert_eq!(options.stream(&record), 0x401000);
+        let options = ReplayOptions { per_pc: false, ..options };
+        assert_eq!(options.stream(&record), 0);
+    }
+
+    #[test]
+    fn test_analyze_streams_and_regions() {
+        // Three instructions interleaved: a unit-stride scan, a 4-line stride
+        // and an irregular walk that repeats
+        let probes = [907u64, 13, 5521, 340, 72, 8810, 1999, 46];
+        let mut records = Vec::new();
+        for i in 0..400u64 {
+            let timestamp = records.len() as u64;
+            records.push(TraceRecord { timestamp, pc: 0x10, address: 0x10_0000 + i * 64, ..Default::default() });
+            records.push(TraceRecord { timestamp, pc: 0x20, address: 0x80_0000 + i * 256, ..Default::default() });
+            let address = 0x400_0000 + probes[i as usize % probes.len()] * 64;
+            records.push(TraceRecord { timestamp, pc: 0x30, address, ..Default::default() });
+        }
+        let options = ReplayOptions::lines(64);
+        let build = || PredictivePrefetcher::<u64>::new(8);
+
+        let analysis = analyze(records.iter().copied().map(Ok), options, Partition::Stream, build).unwrap();
+        println!("{:#?}", analysis.parts.iter().map(|p| (p.key, &p.pattern_type)).collect::<Vec<_>>());
+        assert_eq!(analysis.records, 1200);
+        assert_eq!(analysis.parts.len(), 3);
+        let pattern = |key: u64| analysis.parts.iter().find(|p| p.key == key).unwrap().pattern_type.clone();
+        assert_eq!(pattern(0x10), PatternType::Sequential);
+        assert_eq!(pattern(0x20), PatternType::Strided);
+        assert_eq!(pattern(0x30), PatternType::Markov);
+        for part in &analysis.parts {
+            assert_eq!(part.accesses, 400);
+            assert_eq!(part.stats.demand_accesses, 400);
+            assert_eq!(part.patterns.iter().map(|&(_, n)| n).sum::<u64>(), 400);
+        }
+
+        // The same accesses split into 1 MiB regions
+        let analysis = analyze(records.iter().copied().map(Ok), options, Partition::Region(1 << 20), build).unwrap();
+        let keys: Vec<u64> = analysis.parts.iter().map(|p| p.key).collect();
+        assert!(keys.contains(&(0x10_0000 >> 20)) && keys.contains(&(0x400_0000 >> 20)));
+        assert_eq!(analysis.parts.iter().map(|p| p.accesses).sum::<u64>(), 1200);
+        assert!(analysis.parts.windows(2).all(|w| w[0].accesses >= w[1].accesses));
+
+        // Malformed records stop the analysis
+        let broken = vec![Ok(records[0]), Err(TraceError::Parse { record: 2, message: "bad".to_string() })];
+        assert!(analyze(broken, options, Partition::Stream, build).is_err());
+    }
+}